@@ -1,3 +1,4 @@
+mod piece_parse;
 mod route_parse;
 
 use syn::{parse_macro_input, DeriveInput};
@@ -19,11 +20,284 @@ enum MyRoute {
 }
 ```
 
+A `struct` with a single `route` attribute can also be used, e.g.:
+
+```ignore
+#[derive(Route, Clone, PartialEq, Debug)]
+#[route("/users/{id}")]
+struct UserRoute {
+    id: u64,
+}
+```
+
+Named placeholders such as `{name}` are matched to fields by name, so their
+order need not follow the field order, but no field may be used twice. A
+placeholder naming a missing field is a compile error, with a suggestion if a
+field has a similar name:
+
+```text
+error: no field named nmae, did you mean name?
+ --> src/main.rs:5:13
+  |
+5 |     #[route("/hello/{nmae}")]
+  |             ^^^^^^^^^^^^^^^
+```
+
+Note that the error covers the whole route string rather than just the
+placeholder, since pointing inside a string literal requires
+`proc_macro::Literal::subspan`, which is not available on stable Rust.
+
+A path segment may also surround a single field with literal text, which is
+removed before parsing the field and added back when rendering:
+
+```ignore
+#[route("/files/{name}.json")]
+Json { name: String },
+#[route("/v{}/status")]
+Status(u32),
+```
+
+A field marked with `#[route(validate = "...")]` is passed by reference to the
+named function after parsing. If it returns `false`, the variant does not
+match and the next one is tried:
+
+```ignore
+fn is_numeric(s: &str) -> bool {
+    s.bytes().all(|b| b.is_ascii_digit())
+}
+
+#[route("/user/{id}")]
+UserId {
+    #[route(validate = "is_numeric")]
+    id: String,
+},
+#[route("/user/{name}")]
+UserName { name: String },
+```
+
+Variants are tried in order, and the first one to match wins. A variant which
+can never match, because an earlier variant matches all of the same requests,
+is a compile error, unless it is marked with `#[route(allow_overlap)]`:
+
+```ignore
+#[route("/hello/{name}")]
+Hello { name: String },
+#[route("/hello/admin")]
+#[route(allow_overlap)]
+Admin,
+```
+
+Only fields which can't fail to parse, i.e. `String`, `Cow<str>`, and `Option`s
+or `Vec`s of those, count as matching everything. Variants with any other field,
+a nested route or a `validate` function may fall through to later ones, so e.g.
+`/user/{}` with an `i32` before `/user/new` is allowed.
+
+A route may be restricted to some HTTP methods by listing them before the
+path. `GET` also allows `HEAD`. These are only checked by the method aware
+parsers such as `Route::parse_method`, which fall through to later variants
+and give `RouteError::MethodNotAllowed` if only the method failed to match.
+Variants with the same path but different methods don't overlap:
+
+```ignore
+#[route(GET, "/items")]
+ListItems,
+#[route(POST, "/items")]
+CreateItem,
+```
+
+A variant may also be restricted to a host with a separate
+`#[route(host = "...")]` attribute. Its labels work like path segments, and
+their fields come before those of the path. Hosts are matched case
+insensitively and without the port, by parsers given a host such as
+`Route::parse_request`. Like methods, a host without fields isn't checked
+when parsing without a host, e.g. with `Route::parse_str`, but a host with
+fields never matches then. `Route::host` renders the host back in lowercase,
+and `Route::render_from_host` gives a scheme relative URL, keeping the port,
+when it differs from the current one. `Route::render_absolute` gives a full
+URL instead:
+
+```ignore
+#[route(host = "{tenant}.example.com")]
+#[route("/dashboard")]
+Dashboard { tenant: String },
+```
+
+The final path segment may be a rest capture, which consumes all remaining
+segments using `RouteMultiPiece`, e.g.:
+
+```ignore
+#[route("/static/{path*}")]
+Static { path: Vec<String> },
+```
+
+Marking the rest capture with `#[route(nest)]` embeds another `Route` type,
+which receives the remaining path segments and the query string. The nested
+type should use the same trailing slash policy (see below) as the outer type:
+
+```ignore
+#[route("/admin/{sub*}")]
+Admin {
+    #[route(nest)]
+    sub: AdminRoute,
+},
+```
+
+Query string fields are required by default. A field with an `Option` type
+may be absent, and is omitted when rendering a `None`. A field marked with
+`#[route(default = ...)]` uses the given value when absent, and is omitted
+when rendering a value equal to it, which requires `PartialEq`. A bare
+`#[route(default)]` uses `Default::default()`:
+
+```ignore
+#[route("/search?q={q}&page={page}&lang={lang}")]
+Search {
+    q: String,
+    #[route(default = 1)]
+    page: u32,
+    lang: Option<Lang>,
+},
+```
+
+A `Vec` query string field collects the values of every occurrence of its
+key, and renders one pair per element. Alternatively, a field marked with
+`#[route(separator = ",")]` parses a single value split on the separator, and
+renders the elements joined by it. Both work with any `RouteMultiPiece` and
+are empty when the key is absent. The separator and `%` are percent-encoded
+within each element, so that e.g. `"a,b"` stays a single element, and an
+empty value such as `?ids=` is a single empty element:
+
+```ignore
+#[route("/posts?tag={tags}&ids={ids}")]
+Posts {
+    tags: Vec<String>,
+    #[route(separator = ",")]
+    ids: Vec<u32>,
+},
+```
+
+Query string parameters not mentioned in a variant's route are ignored by
+default. A `Vec<(String, Option<String>)>` field marked with
+`#[route(query_rest)]` captures them instead, and they are rendered after the
+parameters from the route. In a tuple variant, it must come
+after all fields used by the route:
+
+```ignore
+#[route("/landing?campaign={campaign}")]
+Landing {
+    campaign: String,
+    #[route(query_rest)]
+    extra: Vec<(String, Option<String>)>,
+},
+```
+
+Fields marked with `#[route(flatten)]` are parsed from the same query string
+using `RouteQuery`, see `derive(RouteQuery)`. In a tuple variant, these must
+come after all fields used by the route.
+
+The normalization rules can be changed for the whole type with a comma
+separated list of policies:
+
+```ignore
+#[derive(Route, Clone, PartialEq, Debug)]
+#[route_config(normalize = "trailing_slash, case_insensitive")]
+enum MyRoute {
+    #[route("/docs")]
+    Docs,
+}
+```
+
+The available policies are `no_trailing_slash` (the default),
+`trailing_slash`, `ignore_trailing_slash`, `keep_duplicate_slashes`, and
+`case_insensitive`.
+
+`#[route_config(deny_unknown_query)]` makes variants without a `query_rest`
+field fail to match when the query string contains parameters not mentioned
+in their route.
+
+Derived types also implement `Display`, writing the same output as
+`Route::render` without building the intermediate `Vec`s of `Route::path`.
+Use `#[route_config(no_display)]` to provide your own implementation instead.
+
+`#[route_prefix("/api/v2")]` prepends literal path segments to every route of
+the type, for both parsing and rendering. A route of `/` then matches the
+prefix itself. To choose the prefix at runtime instead, see
+`routetype::Mount`.
+
 */
-#[proc_macro_derive(Route, attributes(route))]
+#[proc_macro_derive(Route, attributes(route, route_config, route_prefix))]
 pub fn derive_route(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
-    let routes =
-        route_parse::Routes::parse(&input).expect("Could not parse attributes for route deriving");
-    proc_macro::TokenStream::from(routes.gen_impl())
+    match route_parse::Routes::parse(&input) {
+        Ok(routes) => proc_macro::TokenStream::from(routes.gen_impl()),
+        Err(e) => proc_macro::TokenStream::from(e.to_compile_error()),
+    }
+}
+
+/** Derive the `RoutePiece` trait for the given type.
+
+For an `enum` with only unit variants, each variant is rendered as its name in
+kebab-case. This can be overridden with a `piece` attribute:
+
+```ignore
+#[derive(RoutePiece, Clone, Copy, PartialEq, Debug)]
+enum Lang {
+    En,
+    He,
+    #[piece("fr-ca")]
+    CanadianFrench,
+}
+```
+
+For a tuple `struct` with a single field, parsing and rendering are delegated
+to the inner type:
+
+```ignore
+#[derive(RoutePiece, Clone, PartialEq, Debug)]
+struct UserId(u64);
+```
+
+*/
+#[proc_macro_derive(RoutePiece, attributes(piece))]
+pub fn derive_route_piece(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match piece_parse::Piece::parse(&input) {
+        Ok(piece) => proc_macro::TokenStream::from(piece.gen_impl()),
+        Err(e) => proc_macro::TokenStream::from(e.to_compile_error()),
+    }
+}
+
+/** Derive the `RouteQuery` trait for the given `struct`.
+
+This describes a reusable set of query string parameters, using the same
+syntax as the query string of a `Route`:
+
+```ignore
+#[derive(RouteQuery, Clone, PartialEq, Debug)]
+#[route("page={page}&per_page={per_page}")]
+struct Pagination {
+    #[route(default = 1)]
+    page: u32,
+    #[route(default = 20)]
+    per_page: u32,
+}
+```
+
+It can be embedded in any number of routes with `#[route(flatten)]`:
+
+```ignore
+#[route("/posts")]
+Posts {
+    #[route(flatten)]
+    pagination: Pagination,
+},
+```
+
+*/
+#[proc_macro_derive(RouteQuery, attributes(route))]
+pub fn derive_route_query(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match route_parse::QueryFields::parse(&input) {
+        Ok(fields) => proc_macro::TokenStream::from(fields.gen_impl()),
+        Err(e) => proc_macro::TokenStream::from(e.to_compile_error()),
+    }
 }
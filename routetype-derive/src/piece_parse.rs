@@ -0,0 +1,179 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{spanned::Spanned, DeriveInput, Error, Ident, Result};
+
+/// A user defined type deriving `RoutePiece`.
+pub struct Piece<'a> {
+    input: &'a DeriveInput,
+    kind: PieceKind,
+}
+
+/// How the value is rendered.
+enum PieceKind {
+    /// A unit-only `enum`, each variant rendered as a fixed string.
+    Enum(Vec<(Ident, String)>),
+    /// A single-field tuple `struct`, delegating to the inner type.
+    Newtype(Box<syn::Type>),
+}
+
+impl<'a> Piece<'a> {
+    /// Parse a `Piece` value from user supplied input.
+    pub fn parse(input: &'a DeriveInput) -> Result<Self> {
+        let kind =
+            match &input.data {
+                syn::Data::Enum(data) => {
+                    if data.variants.is_empty() {
+                        return Err(Error::new(
+                            input.ident.span(),
+                            "RoutePiece cannot be derived for empty enums",
+                        ));
+                    }
+                    let mut variants: Vec<(Ident, String)> = Vec::new();
+                    for variant in &data.variants {
+                        if !matches!(variant.fields, syn::Fields::Unit) {
+                            return Err(Error::new(
+                                variant.fields.span(),
+                                "RoutePiece can only be derived for enums with unit variants",
+                            ));
+                        }
+                        let s = match piece_attr(&variant.attrs)? {
+                            Some(s) => s,
+                            None => kebab_case(&variant.ident.to_string()),
+                        };
+                        if let Some((other, _)) = variants.iter().find(|(_, other)| *other == s) {
+                            return Err(Error::new(
+                                variant.ident.span(),
+                                format!(
+                                    "Variants {} and {} both use the string {:?}",
+                                    other, variant.ident, s
+                                ),
+                            ));
+                        }
+                        variants.push((variant.ident.clone(), s));
+                    }
+                    PieceKind::Enum(variants)
+                }
+                syn::Data::Struct(data) => match &data.fields {
+                    syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                        PieceKind::Newtype(Box::new(fields.unnamed[0].ty.clone()))
+                    }
+                    _ => return Err(Error::new(
+                        input.ident.span(),
+                        "RoutePiece can only be derived for structs with a single unnamed field",
+                    )),
+                },
+                syn::Data::Union(_) => {
+                    return Err(Error::new(
+                        input.ident.span(),
+                        "RoutePiece cannot be derived for unions",
+                    ))
+                }
+            };
+        Ok(Piece { input, kind })
+    }
+
+    /// Generate the full `impl RoutePiece` for this type
+    pub fn gen_impl(&self) -> TokenStream {
+        let ident = &self.input.ident;
+        let (impl_generics, ty_generics, where_clause) = self.input.generics.split_for_impl();
+        match &self.kind {
+            PieceKind::Enum(variants) => {
+                let parse_arms = variants.iter().map(|(variant, s)| {
+                    quote! { #s => Ok(#ident::#variant), }
+                });
+                let render_arms = variants.iter().map(|(variant, s)| {
+                    quote! { #ident::#variant => #s, }
+                });
+                let expected = format!(
+                    "one of {}",
+                    variants
+                        .iter()
+                        .map(|(_, s)| format!("{:?}", s))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+                quote! {
+                    impl #impl_generics routetype::RoutePiece for #ident #ty_generics #where_clause {
+                        fn parse_route_piece(s: &str) -> Option<Self> {
+                            routetype::RoutePiece::parse_route_piece_diagnostic(s).ok()
+                        }
+
+                        fn parse_route_piece_diagnostic(s: &str) -> std::result::Result<Self, Option<String>> {
+                            match s {
+                                #(#parse_arms)*
+                                _ => Err(Some(routetype::InvalidRoutePiece(#expected).to_string())),
+                            }
+                        }
+
+                        fn render_route_piece(&self) -> std::borrow::Cow<'_, str> {
+                            std::borrow::Cow::Borrowed(match self {
+                                #(#render_arms)*
+                            })
+                        }
+                    }
+                }
+            }
+            PieceKind::Newtype(ty) => {
+                let mut where_clause = where_clause.cloned().unwrap_or_else(|| syn::WhereClause {
+                    where_token: Default::default(),
+                    predicates: Default::default(),
+                });
+                where_clause
+                    .predicates
+                    .push(syn::parse_quote!(#ty: routetype::RoutePiece));
+                quote! {
+                    impl #impl_generics routetype::RoutePiece for #ident #ty_generics #where_clause {
+                        fn parse_route_piece(s: &str) -> Option<Self> {
+                            <#ty as routetype::RoutePiece>::parse_route_piece(s).map(#ident)
+                        }
+
+                        fn parse_route_piece_diagnostic(s: &str) -> std::result::Result<Self, Option<String>> {
+                            <#ty as routetype::RoutePiece>::parse_route_piece_diagnostic(s).map(#ident)
+                        }
+
+                        fn render_route_piece(&self) -> std::borrow::Cow<'_, str> {
+                            routetype::RoutePiece::render_route_piece(&self.0)
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Extract the string from a `#[piece("...")]` attribute, if present.
+fn piece_attr(attrs: &[syn::Attribute]) -> Result<Option<String>> {
+    for attr in attrs {
+        if attr.path.is_ident("piece") {
+            return match attr.parse_args::<syn::LitStr>() {
+                Ok(lit) => Ok(Some(lit.value())),
+                Err(_) => Err(Error::new(
+                    attr.span(),
+                    "piece attribute must be a string literal",
+                )),
+            };
+        }
+    }
+    Ok(None)
+}
+
+/// Convert a `CamelCase` identifier into `kebab-case`, keeping acronyms together, e.g. `HttpsURL` becomes `https-url`.
+fn kebab_case(ident: &str) -> String {
+    let chars: Vec<char> = ident.chars().collect();
+    let mut res = String::new();
+    for (idx, c) in chars.iter().enumerate() {
+        if c.is_uppercase() && idx > 0 {
+            let prev = chars[idx - 1];
+            let next_lower = matches!(chars.get(idx + 1), Some(c) if c.is_lowercase());
+            if prev.is_lowercase() || prev.is_ascii_digit() || (prev.is_uppercase() && next_lower) {
+                res.push('-');
+            }
+        }
+        if *c == '_' {
+            res.push('-');
+        } else {
+            res.extend(c.to_lowercase());
+        }
+    }
+    res
+}
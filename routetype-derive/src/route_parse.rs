@@ -1,17 +1,25 @@
-use std::{convert::Infallible, str::FromStr};
+use std::convert::Infallible;
 
-use anyhow::*;
-use proc_macro2::TokenStream;
+use proc_macro2::{Span, TokenStream};
 use quote::{format_ident, quote, TokenStreamExt};
-use syn::{DeriveInput, Ident};
+use syn::{spanned::Spanned, DeriveInput, Error, Ident, Result};
 
-/// Represents the fields and attributes of a single user defined `enum` route type.
+/// Return early with a [syn::Error] at the given span.
+macro_rules! bail {
+    ($span:expr, $($arg:tt)*) => {
+        return Err(Error::new($span, format!($($arg)*)))
+    };
+}
+
+/// Represents the fields and attributes of a single user defined route type, either an `enum` or a `struct`.
 #[derive(Debug)]
 pub struct Routes {
     /// Name of the data type
     ident: Ident,
-    /// Each of the variants/routes
+    /// Each of the variants/routes, a `struct` has exactly one
     routes: Vec<Route>,
+    /// Type-level settings from `#[route_config(...)]`
+    config: Config,
 }
 
 impl Routes {
@@ -19,27 +27,188 @@ impl Routes {
     ///
     /// This should follow the principle of failing early, returning a helpful error message on any invalid input.
     pub fn parse(input: &DeriveInput) -> Result<Self> {
-        let data = match &input.data {
-            syn::Data::Enum(data) => data,
-            _ => bail!("Derive macro can only be used on enums"),
+        let prefix = route_prefix_attr(&input.attrs)?;
+        let prefix = prefix.as_deref();
+        let routes = match &input.data {
+            syn::Data::Enum(data) => data
+                .variants
+                .iter()
+                .map(|variant| {
+                    let ident = &variant.ident;
+                    Route::parse(
+                        ident,
+                        quote! { Self::#ident },
+                        &variant.attrs,
+                        &variant.fields,
+                        prefix,
+                    )
+                })
+                .collect::<Result<_>>()?,
+            syn::Data::Struct(data) => vec![Route::parse(
+                &input.ident,
+                quote! { Self },
+                &input.attrs,
+                &data.fields,
+                prefix,
+            )?],
+            syn::Data::Union(_) => bail!(
+                input.ident.span(),
+                "Derive macro can only be used on enums and structs"
+            ),
         };
 
-        Ok(Routes {
+        let routes = Routes {
             ident: input.ident.clone(),
-            routes: data
-                .variants
+            config: Config::parse(&input.attrs)?,
+            routes,
+        };
+        routes.check_overlaps()?;
+        Ok(routes)
+    }
+
+    /// Reject variants which can never match, because an earlier variant matches all of the same requests.
+    ///
+    /// Only variants whose fields can't fail to parse, such as `String`s, can shadow later ones, since otherwise the earlier variant may fall through to the later one.
+    fn check_overlaps(&self) -> Result<()> {
+        let mut res: Option<Error> = None;
+        for (idx, later) in self.routes.iter().enumerate() {
+            if later.allow_overlap {
+                continue;
+            }
+            let earlier = self.routes[..idx]
                 .iter()
-                .map(Route::parse)
-                .collect::<Result<_>>()?,
-        })
+                .find(|earlier| earlier.covers(later, &self.config));
+            if let Some(earlier) = earlier {
+                let e = Error::new(
+                    later.span,
+                    format!(
+                        "{} can never match, {} is declared first and matches all of the same requests. Reorder the variants, or add #[route(allow_overlap)] if this is intentional",
+                        later.ident, earlier.ident
+                    ),
+                );
+                match &mut res {
+                    None => res = Some(e),
+                    Some(res) => res.combine(e),
+                }
+            }
+        }
+        match res {
+            None => Ok(()),
+            Some(e) => Err(e),
+        }
     }
 
     /// Generate the full `impl Route` for this type
+    ///
+    /// The parse logic is shared between `parse` and `parse_diagnostic` via a private helper trait, so everything is wrapped up in an anonymous `const`.
     pub fn gen_impl(&self) -> TokenStream {
+        let parse_diagnose = self.gen_parse_diagnose();
+        let route_impl = self.gen_route_impl();
+        let ident = &self.ident;
+        let display_impl = if self.config.no_display {
+            TokenStream::new()
+        } else {
+            quote! {
+                impl std::fmt::Display for #ident {
+                    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                        routetype::Route::render_to(self, f)
+                    }
+                }
+            }
+        };
+        quote! {
+            const _: () = {
+                #parse_diagnose
+                #route_impl
+                #display_impl
+            };
+        }
+    }
+
+    /// Generate the private `ParseDiagnose` trait and its `impl`, containing the real parse logic.
+    fn gen_parse_diagnose(&self) -> TokenStream {
+        let ident = &self.ident;
+        let parse_tree = self.gen_parse_tree();
+        let normalization = self.config.gen_normalization();
+        // Only split up the host if some variant needs it
+        let host_labels = if self.routes.iter().any(|route| route.host_shape().is_some()) {
+            quote! {
+                let host = request.host.map(routetype::raw::normalize_host);
+                let host: Option<Vec<&str>> = host.as_deref().map(|host| host.split('.').collect());
+            }
+        } else {
+            TokenStream::new()
+        };
+        // Nested routes and query_rest fields need the original query string pairs, not just the QueryMap
+        let collect_query = if self.routes.iter().any(Route::needs_query_pairs) {
+            quote! {
+                let query_pairs: Option<Vec<routetype::QueryPair>> = query.map(Iterator::collect);
+                let query = query_pairs.as_ref().map(|query| query.iter().cloned());
+            }
+        } else {
+            TokenStream::new()
+        };
+        // The QueryMap is only built once a variant with query string fields matches the path, see Route::gen_parse_block
+        let query_map = if self
+            .routes
+            .iter()
+            .any(|route| route.needs_query_map(&self.config))
+        {
+            quote! {
+                let mut pending_query = query;
+                let mut query_map: Option<routetype::QueryMap> = None;
+            }
+        } else {
+            quote! {
+                let _ = query;
+            }
+        };
+
+        quote! {
+            trait ParseDiagnose: Sized {
+                fn parse_diagnose<'a, 'b>(
+                    request: routetype::RequestInfo<'_>,
+                    path: impl Iterator<Item = routetype::PathSegment<'a>>,
+                    query: Option<impl Iterator<Item = routetype::QueryPair<'b>>>,
+                    diagnose: bool,
+                ) -> Result<Self, routetype::RouteError>;
+            }
+
+            impl ParseDiagnose for #ident {
+                fn parse_diagnose<'a, 'b>(
+                    request: routetype::RequestInfo<'_>,
+                    path: impl Iterator<Item = routetype::PathSegment<'a>>,
+                    query: Option<impl Iterator<Item = routetype::QueryPair<'b>>>,
+                    diagnose: bool,
+                ) -> Result<Self, routetype::RouteError> {
+                    #normalization
+                    let (path, query) = NORMALIZATION.normalize_parse_inline(path, query)
+                        .map_err(routetype::RouteError::NormalizationFailed)?;
+                    #collect_query
+                    #host_labels
+                    #query_map
+                    let mut closest = routetype::diagnostic::ClosestMatch::default();
+                    let mut allowed: Vec<&'static str> = Vec::new();
+                    #parse_tree
+                    if allowed.is_empty() {
+                        Err(closest.into_error())
+                    } else {
+                        Err(routetype::RouteError::method_not_allowed(allowed))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Generate the `impl Route` itself, relying on the `ParseDiagnose` helper.
+    fn gen_route_impl(&self) -> TokenStream {
         let ident = &self.ident;
         let path_arms = self.gen_path_arms();
         let query_arms = self.gen_query_arms();
-        let parse_blocks = self.gen_parse_blocks();
+        let host_fn = self.gen_host_fn();
+        let render_arms = self.gen_render_arms();
+        let render_query_arms = self.gen_render_query_arms();
+        let normalization = self.config.gen_normalization();
 
         quote! {
             impl routetype::Route for #ident {
@@ -47,20 +216,39 @@ impl Routes {
                     path: impl Iterator<Item = routetype::PathSegment<'a>>,
                     query: Option<impl Iterator<Item = routetype::QueryPair<'b>>>,
                 ) -> Result<Self, routetype::RouteError> {
-                    // We should use a more efficient parsing tree approach like in Yesod
-                    let (path, query) = routetype::normalize::Normalization::default().normalize_parse(path, query)
-                        .map_err(routetype::RouteError::NormalizationFailed)?;
-                    let query = routetype::QueryMap::from_query_iter(query);
-                    #parse_blocks
-                    Err(routetype::RouteError::NoMatch)
+                    <Self as ParseDiagnose>::parse_diagnose(Default::default(), path, query, false)
+                }
+
+                fn parse_diagnostic<'a, 'b>(
+                    path: impl Iterator<Item = routetype::PathSegment<'a>>,
+                    query: Option<impl Iterator<Item = routetype::QueryPair<'b>>>,
+                ) -> Result<Self, routetype::RouteError> {
+                    <Self as ParseDiagnose>::parse_diagnose(Default::default(), path, query, true)
+                }
+
+                fn parse_request<'a, 'b>(
+                    request: routetype::RequestInfo<'_>,
+                    path: impl Iterator<Item = routetype::PathSegment<'a>>,
+                    query: Option<impl Iterator<Item = routetype::QueryPair<'b>>>,
+                ) -> Result<Self, routetype::RouteError> {
+                    <Self as ParseDiagnose>::parse_diagnose(request, path, query, false)
+                }
+
+                fn parse_request_diagnostic<'a, 'b>(
+                    request: routetype::RequestInfo<'_>,
+                    path: impl Iterator<Item = routetype::PathSegment<'a>>,
+                    query: Option<impl Iterator<Item = routetype::QueryPair<'b>>>,
+                ) -> Result<Self, routetype::RouteError> {
+                    <Self as ParseDiagnose>::parse_diagnose(request, path, query, true)
                 }
 
                 fn path(&self) -> Vec<routetype::PathSegment> {
                     let mut res = Vec::new();
+                    #normalization
                     match self {
                         #path_arms
                     };
-                    routetype::normalize::Normalization::default().normalize_render_path(res)
+                    NORMALIZATION.normalize_render_path(res)
                 }
 
                 fn query(&self) -> Option<Vec<routetype::QueryPair>> {
@@ -74,6 +262,42 @@ impl Routes {
                         Some(res)
                     }
                 }
+
+                #host_fn
+
+                fn render_to<W: std::fmt::Write + ?Sized>(&self, w: &mut W) -> std::fmt::Result {
+                    let mut writer = routetype::raw::UrlWriter::new(w);
+                    routetype::Route::render_path_to(self, &mut writer)?;
+                    routetype::Route::render_query_to(self, &mut writer)?;
+                    writer.finish()
+                }
+
+                fn render_path_to<W: std::fmt::Write + ?Sized>(
+                    &self,
+                    writer: &mut routetype::raw::UrlWriter<'_, W>,
+                ) -> std::fmt::Result {
+                    #normalization
+                    // Segments before ours belong to an outer route, if we're nested
+                    let start = writer.segments();
+                    // A nested route which wrote any segments has already normalized its own path
+                    let nested = match self {
+                        #render_arms
+                    };
+                    if !nested && writer.segments() > start {
+                        NORMALIZATION.finish_render_path(writer)?;
+                    }
+                    Ok(())
+                }
+
+                fn render_query_to<W: std::fmt::Write + ?Sized>(
+                    &self,
+                    writer: &mut routetype::raw::UrlWriter<'_, W>,
+                ) -> std::fmt::Result {
+                    match self {
+                        #render_query_arms
+                    }
+                    Ok(())
+                }
             }
         }
     }
@@ -90,53 +314,418 @@ impl Routes {
         res
     }
 
-    /// Generate the match arms within the `query` method implementation
-    fn gen_query_arms(&self) -> TokenStream {
+    /// Generate the match arms within the `render_path_to` method implementation
+    fn gen_render_arms(&self) -> TokenStream {
+        let mut res = TokenStream::new();
+        for route in &self.routes {
+            let pattern = route.gen_pattern();
+            let render_stmts = route.render_arm_stmts();
+
+            res.append_all(quote! { #pattern => { #render_stmts } });
+        }
+        res
+    }
+
+    /// Generate the match arms within the `render_query_to` method implementation
+    fn gen_render_query_arms(&self) -> TokenStream {
         let mut res = TokenStream::new();
         for route in &self.routes {
             let pattern = route.gen_pattern();
-            let query_stmts = route.query_arm_stmts();
+            let query_stmts = route.query_arm_stmts(QuerySink::Writer);
 
             res.append_all(quote! { #pattern => { #query_stmts } });
         }
         res
     }
 
-    /// Generate the individual parse blocks within the `parse` method implementation
-    fn gen_parse_blocks(&self) -> TokenStream {
+    /// Generate the match arms within the `query` method implementation
+    fn gen_query_arms(&self) -> TokenStream {
         let mut res = TokenStream::new();
         for route in &self.routes {
-            route.gen_parse_block(&mut res);
+            let pattern = route.gen_pattern();
+            let query_stmts = route.query_arm_stmts(QuerySink::Vec);
+
+            res.append_all(quote! { #pattern => { #query_stmts } });
         }
         res
     }
+
+    /// Generate the `host` method implementation, if any variant is restricted to a host.
+    fn gen_host_fn(&self) -> TokenStream {
+        if self.routes.iter().all(|route| route.host_shape().is_none()) {
+            return TokenStream::new();
+        }
+        let mut arms = TokenStream::new();
+        for route in &self.routes {
+            let pattern = route.gen_pattern();
+            let host = route.route_contents.host_expr();
+            arms.append_all(quote! { #pattern => #host, });
+        }
+        quote! {
+            fn host(&self) -> Option<String> {
+                match self {
+                    #arms
+                }
+            }
+        }
+    }
+
+    /// Generate the parse tree within the `parse` method implementation.
+    ///
+    /// Instead of trying each variant in turn, we dispatch on the literal path segments one level at a time, similar to Yesod. Variants with a field in a given position are included in every branch at that level, and variants with a rest capture are included in every branch below it. Candidates are always kept in declaration order, so the first matching variant still wins.
+    fn gen_parse_tree(&self) -> TokenStream {
+        let shapes: Vec<_> = self.routes.iter().map(Route::path_shape).collect();
+        let candidates: Vec<usize> = (0..self.routes.len()).collect();
+        self.gen_parse_node(&shapes, 0, &candidates)
+    }
+
+    /// Generate a single node of the parse tree, for the segment at `depth`.
+    ///
+    /// `candidates` are indices into `routes` of all variants which may still match.
+    fn gen_parse_node(
+        &self,
+        shapes: &[Vec<SegShape>],
+        depth: usize,
+        candidates: &[usize],
+    ) -> TokenStream {
+        if candidates.is_empty() {
+            return TokenStream::new();
+        }
+
+        // Variants which end at this depth, or which capture everything from here on
+        let mut done = TokenStream::new();
+        for &idx in candidates {
+            if matches!(shape_at(&shapes[idx], depth), None | Some(SegShape::Rest)) {
+                self.routes[idx].gen_parse_block(&self.config, &mut done);
+            }
+        }
+
+        // Only rest captures are left, no need to look at any more segments
+        if candidates
+            .iter()
+            .all(|&idx| matches!(shape_at(&shapes[idx], depth), Some(SegShape::Rest)))
+        {
+            return done;
+        }
+
+        // Each distinct literal at this depth, in order of first appearance
+        let mut literals: Vec<&str> = Vec::new();
+        for &idx in candidates {
+            if let Some(SegShape::Literal(lit)) = shape_at(&shapes[idx], depth) {
+                if !literals
+                    .iter()
+                    .any(|other| self.config.literals_equal(other, lit))
+                {
+                    literals.push(lit);
+                }
+            }
+        }
+
+        let mut arms = TokenStream::new();
+        for lit in literals {
+            let next: Vec<usize> = candidates
+                .iter()
+                .copied()
+                .filter(|&idx| match shape_at(&shapes[idx], depth) {
+                    Some(SegShape::Literal(other)) => self.config.literals_equal(other, lit),
+                    Some(SegShape::Field)
+                    | Some(SegShape::Affixed { .. })
+                    | Some(SegShape::Rest) => true,
+                    None => false,
+                })
+                .collect();
+            let node = self.gen_parse_node(shapes, depth + 1, &next);
+            arms.append_all(if self.config.case_insensitive {
+                quote! { Some(seg) if seg.eq_ignore_ascii_case(#lit) => { #node } }
+            } else {
+                quote! { Some(#lit) => { #node } }
+            });
+        }
+
+        let fields: Vec<usize> = candidates
+            .iter()
+            .copied()
+            .filter(|&idx| {
+                matches!(
+                    shape_at(&shapes[idx], depth),
+                    Some(SegShape::Field) | Some(SegShape::Affixed { .. }) | Some(SegShape::Rest)
+                )
+            })
+            .collect();
+        let fields = self.gen_parse_node(shapes, depth + 1, &fields);
+
+        quote! {
+            match path.get(#depth).map(|seg| &**seg) {
+                None => { #done }
+                #arms
+                Some(_) => { #fields }
+            }
+        }
+    }
+}
+
+/// Type-level settings, provided by the `route_config` attribute on the `enum`.
+#[derive(Debug, Default)]
+struct Config {
+    /// Variant name of `TrailingSlash`, if not the default
+    trailing_slash: Option<&'static str>,
+    /// Leave repeated slashes alone instead of redirecting
+    keep_duplicate_slashes: bool,
+    /// Match literal segments ignoring ASCII case
+    case_insensitive: bool,
+    /// Query string keys not mentioned in a variant's route cause it not to match
+    deny_unknown_query: bool,
+    /// Don't implement `Display`, e.g. because the type has its own implementation
+    no_display: bool,
+}
+
+impl Config {
+    /// Parse all `route_config` attributes, e.g. `#[route_config(normalize = "trailing_slash")]`.
+    fn parse(attrs: &[syn::Attribute]) -> Result<Self> {
+        let mut config = Config::default();
+        for attr in attrs {
+            if !attr.path.is_ident("route_config") {
+                continue;
+            }
+            let list = match attr.parse_meta()? {
+                syn::Meta::List(list) => list,
+                meta => bail!(
+                    meta.span(),
+                    "route_config attribute must contain a list of settings"
+                ),
+            };
+            for nested in list.nested {
+                match nested {
+                    syn::NestedMeta::Meta(syn::Meta::NameValue(nv))
+                        if nv.path.is_ident("normalize") =>
+                    {
+                        match nv.lit {
+                            syn::Lit::Str(policies) => config.add_policies(&policies)?,
+                            lit => bail!(lit.span(), "normalize setting must be a string literal"),
+                        }
+                    }
+                    syn::NestedMeta::Meta(syn::Meta::Path(path))
+                        if path.is_ident("deny_unknown_query") =>
+                    {
+                        config.deny_unknown_query = true
+                    }
+                    syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("no_display") => {
+                        config.no_display = true
+                    }
+                    nested => bail!(nested.span(), "Unknown setting in route_config attribute"),
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    /// Add a comma separated list of normalization policies.
+    fn add_policies(&mut self, policies: &syn::LitStr) -> Result<()> {
+        for policy in policies.value().split(',').map(str::trim) {
+            match policy {
+                "no_trailing_slash" => self.trailing_slash = Some("Remove"),
+                "trailing_slash" => self.trailing_slash = Some("Require"),
+                "ignore_trailing_slash" => self.trailing_slash = Some("Ignore"),
+                "keep_duplicate_slashes" => self.keep_duplicate_slashes = true,
+                "case_insensitive" => self.case_insensitive = true,
+                _ => bail!(
+                    policies.span(),
+                    "Unknown normalization policy {:?}, expected one of no_trailing_slash, trailing_slash, ignore_trailing_slash, keep_duplicate_slashes, case_insensitive",
+                    policy
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    /// Generate the `NORMALIZATION` constant used by the generated methods.
+    fn gen_normalization(&self) -> TokenStream {
+        let mut ts = quote! { routetype::normalize::Normalization::new() };
+        if let Some(trailing_slash) = self.trailing_slash {
+            let trailing_slash = format_ident!("{}", trailing_slash);
+            ts.append_all(
+                quote! { .trailing_slash(routetype::normalize::TrailingSlash::#trailing_slash) },
+            );
+        }
+        if self.keep_duplicate_slashes {
+            ts.append_all(
+                quote! { .duplicate_slashes(routetype::normalize::DuplicateSlashes::Keep) },
+            );
+        }
+        if self.case_insensitive {
+            ts.append_all(quote! { .case_insensitive(true) });
+        }
+        quote! {
+            const NORMALIZATION: routetype::normalize::Normalization = #ts;
+        }
+    }
+
+    /// Whether two literal segments will match the same input.
+    fn literals_equal(&self, x: &str, y: &str) -> bool {
+        if self.case_insensitive {
+            x.eq_ignore_ascii_case(y)
+        } else {
+            x == y
+        }
+    }
+
+    /// Same as `Normalization::strip_affixes`, for checking overlaps between routes.
+    fn strip_affixes<'a>(&self, segment: &'a str, prefix: &str, suffix: &str) -> Option<&'a str> {
+        let end = segment.len().checked_sub(suffix.len())?;
+        if end < prefix.len() {
+            return None;
+        }
+        let inner = segment.get(prefix.len()..end)?;
+        if self.literals_equal(&segment[..prefix.len()], prefix)
+            && self.literals_equal(&segment[end..], suffix)
+        {
+            Some(inner)
+        } else {
+            None
+        }
+    }
+}
+
+/// The shape of a route at the given depth, taking rest captures into account.
+fn shape_at<'a>(shape: &[SegShape<'a>], depth: usize) -> Option<SegShape<'a>> {
+    match shape.get(depth) {
+        Some(seg) => Some(*seg),
+        None => match shape.last() {
+            Some(SegShape::Rest) => Some(SegShape::Rest),
+            _ => None,
+        },
+    }
+}
+
+/// Does a route with the `earlier` path match every path matched by the `later` path?
+fn path_covers(earlier: &[SegShape], later: &[SegShape], config: &Config) -> bool {
+    for idx in 0.. {
+        match (earlier.get(idx), later.get(idx)) {
+            // Every path segment from here on matches, including none at all
+            (Some(SegShape::Rest), _) => return true,
+            (None, None) => return true,
+            // The later route also matches shorter or longer paths
+            (None, Some(_)) | (Some(_), None) | (Some(_), Some(SegShape::Rest)) => return false,
+            (Some(SegShape::Literal(x)), Some(SegShape::Literal(y))) => {
+                if !config.literals_equal(x, y) {
+                    return false;
+                }
+            }
+            (Some(SegShape::Literal(_)), Some(_)) => return false,
+            (Some(SegShape::Field), Some(_)) => (),
+            (Some(SegShape::Affixed { prefix, suffix }), Some(SegShape::Literal(y))) => {
+                if config.strip_affixes(y, prefix, suffix).is_none() {
+                    return false;
+                }
+            }
+            (
+                Some(SegShape::Affixed { prefix, suffix }),
+                Some(SegShape::Affixed {
+                    prefix: other_prefix,
+                    suffix: other_suffix,
+                }),
+            ) => {
+                if config.strip_affixes(other_prefix, prefix, "").is_none()
+                    || config.strip_affixes(other_suffix, "", suffix).is_none()
+                {
+                    return false;
+                }
+            }
+            (Some(SegShape::Affixed { .. }), Some(SegShape::Field)) => return false,
+        }
+    }
+    unreachable!()
 }
 
-/// A single variant of a user defined route enum
+/// What overlap detection needs to know about a route's query string.
+struct QueryShape<'a> {
+    /// Keys which must be present, and the value they must have. [None] is a key without a `=`.
+    required: Vec<(&'a str, Option<SegShape<'a>>)>,
+    /// All keys mentioned in the route
+    keys: Vec<&'a str>,
+    /// Any key is accepted, because of a `query_rest` field or a nested route
+    open: bool,
+    /// Flattened fields may use keys which we can't see here
+    flatten: bool,
+}
+
+impl QueryShape<'_> {
+    /// Does a route with this query string match every query string which `later` matches?
+    fn covers(&self, later: &QueryShape, config: &Config) -> bool {
+        if self.flatten {
+            return false;
+        }
+        let required = self.required.iter().all(|(key, value)| {
+            later.required.iter().any(|(other_key, other_value)| {
+                key == other_key
+                    && match (value, other_value) {
+                        (None, None) => true,
+                        (Some(SegShape::Field), Some(_)) => true,
+                        (Some(SegShape::Literal(x)), Some(SegShape::Literal(y))) => x == y,
+                        _ => false,
+                    }
+            })
+        });
+        let known = !config.deny_unknown_query
+            || self.open
+            || (!later.open
+                && !later.flatten
+                && later.keys.iter().all(|key| self.keys.contains(key)));
+        required && known
+    }
+}
+
+/// A single variant of a user defined route enum, or a route struct
 #[derive(Debug)]
 struct Route {
-    /// Name of the variant
+    /// Name of the variant or struct
     ident: Ident,
+    /// Path used to construct and match on this route, e.g. `Self::Home` or `Self`
+    constructor: TokenStream,
     /// The definition of the route
     route_contents: RouteContents,
+    /// Location of the `route` attribute, used for error messages
+    span: Span,
+    /// Set by `#[route(allow_overlap)]`, this route may be shadowed by an earlier one
+    allow_overlap: bool,
+    /// HTTP methods accepted by this route, e.g. `#[route(GET, POST, "/items")]`. Empty means any method.
+    methods: Vec<String>,
 }
 
 impl Route {
-    /// Parse out information on this route from the variant, including the attributes included on it.
-    fn parse(variant: &syn::Variant) -> Result<Self> {
-        let ident = variant.ident.clone();
-        let raw_route: String = raw_route_attr(&variant.attrs).with_context(|| {
-            format!("route attribute is required, missing on variant {}", ident)
-        })?;
-        let variant_type = match &variant.fields {
-            syn::Fields::Named(fields) => RouteContents::parse_named(&raw_route, fields),
-            syn::Fields::Unnamed(fields) => RouteContents::parse_positional(&raw_route, fields),
-            syn::Fields::Unit => RouteContents::parse_unit(&raw_route),
-        }
-        .with_context(|| format!("Parsing fields of route variant {}", ident))?;
+    /// Parse out information on this route from the variant or struct, including the attributes included on it.
+    ///
+    /// `prefix` comes from a `route_prefix` attribute on the type, and is prepended to the route.
+    fn parse(
+        ident: &Ident,
+        constructor: TokenStream,
+        attrs: &[syn::Attribute],
+        fields: &syn::Fields,
+        prefix: Option<&str>,
+    ) -> Result<Self> {
+        let RouteAttr {
+            methods,
+            lit: raw_route,
+        } = match route_attr(attrs)? {
+            Some(attr) => attr,
+            None => bail!(
+                ident.span(),
+                "route attribute is required, missing on {}",
+                ident
+            ),
+        };
         Ok(Route {
-            ident,
-            route_contents: variant_type,
+            ident: ident.clone(),
+            constructor,
+            route_contents: RouteContents::parse(
+                &apply_prefix(prefix, &raw_route.value()),
+                raw_route.span(),
+                fields,
+                host_attr(attrs)?.as_ref(),
+            )?,
+            span: raw_route.span(),
+            allow_overlap: attrs.iter().any(is_allow_overlap),
+            methods: parse_methods(methods)?,
         })
     }
 
@@ -144,18 +733,7 @@ impl Route {
     ///
     /// This will handle the differences between unit, tuple, and field syntax and bind all fields to their derived local names.
     fn gen_pattern(&self) -> TokenStream {
-        let ident = &self.ident;
-        match &self.route_contents {
-            RouteContents::Unit(_) => quote! { Self::#ident },
-            RouteContents::Positional(pq) => {
-                let patterns = pq.patterns();
-                quote! { Self::#ident(#patterns) }
-            }
-            RouteContents::Named(pq) => {
-                let patterns = pq.patterns();
-                quote! { Self::#ident { #patterns } }
-            }
-        }
+        self.route_contents.gen_pattern(&self.constructor)
     }
 
     /// Generate the contents of the match arms of the `path` method.
@@ -173,55 +751,427 @@ impl Route {
         ts
     }
 
-    /// Generate the contents of the match arms of the `query` method.
+    /// Generate the contents of the match arms of the `render_path_to` method.
     ///
-    /// These statements will populate the `query` `Vec`.
-    fn query_arm_stmts(&self) -> TokenStream {
+    /// These statements write the path segments to `writer`, and evaluate to whether the path ends with a non-empty nested route.
+    fn render_arm_stmts(&self) -> TokenStream {
         let mut ts = TokenStream::new();
+        let nested = match &self.route_contents {
+            RouteContents::Unit(pq) => pq.render_stmts(&mut ts),
+            RouteContents::Positional(pq) => pq.render_stmts(&mut ts),
+            RouteContents::Named(pq) => pq.render_stmts(&mut ts),
+        };
+        ts.append_all(quote! { #nested });
+        ts
+    }
+
+    /// Generate the contents of the match arms of the `query` and `render_query_to` methods.
+    ///
+    /// These statements will emit the query string pairs into `sink`.
+    fn query_arm_stmts(&self, sink: QuerySink) -> TokenStream {
+        self.route_contents.query_arm_stmts(sink)
+    }
+
+    /// Does parsing this route need the original query string pairs?
+    fn needs_query_pairs(&self) -> bool {
+        self.route_contents.needs_query_pairs()
+    }
+
+    /// Does parsing this route look up query string parameters by key?
+    fn needs_query_map(&self, config: &Config) -> bool {
+        self.route_contents.needs_query_map(config)
+    }
+
+    /// The shape of this route's path, used for building the parse tree.
+    fn path_shape(&self) -> Vec<SegShape<'_>> {
         match &self.route_contents {
-            RouteContents::Unit(pq) => pq.query.iter().for_each(|query| query.stmts(&mut ts)),
-            RouteContents::Positional(pq) => pq.query.iter().for_each(|query| query.stmts(&mut ts)),
-            RouteContents::Named(pq) => pq.query.iter().for_each(|query| query.stmts(&mut ts)),
+            RouteContents::Unit(pq) => pq.path.iter().map(Seg::shape).collect(),
+            RouteContents::Positional(pq) => pq.path.iter().map(Seg::shape).collect(),
+            RouteContents::Named(pq) => pq.path.iter().map(Seg::shape).collect(),
+        }
+    }
+
+    /// What overlap detection needs to know about this route's query string.
+    fn query_shape(&self) -> QueryShape<'_> {
+        match &self.route_contents {
+            RouteContents::Unit(pq) => pq.query_shape(),
+            RouteContents::Positional(pq) => pq.query_shape(),
+            RouteContents::Named(pq) => pq.query_shape(),
+        }
+    }
+
+    /// Does this route match every request that `later` matches?
+    ///
+    /// Routes with fields which may fail to parse or validate never cover others.
+    fn covers(&self, later: &Route, config: &Config) -> bool {
+        !self.may_fail()
+            && self.covers_methods(later)
+            && self.covers_host(later, config)
+            && path_covers(&self.path_shape(), &later.path_shape(), config)
+            && self.query_shape().covers(&later.query_shape(), config)
+    }
+
+    /// Does this route accept every HTTP method that `later` accepts?
+    fn covers_methods(&self, later: &Route) -> bool {
+        self.methods.is_empty()
+            || (!later.methods.is_empty() && later.methods.iter().all(|m| self.methods.contains(m)))
+    }
+
+    /// Does this route match every host that `later` matches? Hosts are compared label by label, like paths.
+    fn covers_host(&self, later: &Route, config: &Config) -> bool {
+        match (self.host_shape(), later.host_shape()) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(earlier), Some(later)) => path_covers(&earlier, &later, config),
+        }
+    }
+
+    /// The shape of this route's host, if it's restricted to one.
+    fn host_shape(&self) -> Option<Vec<SegShape<'_>>> {
+        match &self.route_contents {
+            RouteContents::Unit(pq) => pq.host_shape(),
+            RouteContents::Positional(pq) => pq.host_shape(),
+            RouteContents::Named(pq) => pq.host_shape(),
+        }
+    }
+
+    /// Could this route fail to match a request once its literals have matched, because of a field?
+    fn may_fail(&self) -> bool {
+        match &self.route_contents {
+            RouteContents::Unit(pq) => pq.may_fail,
+            RouteContents::Positional(pq) => pq.may_fail,
+            RouteContents::Named(pq) => pq.may_fail,
         }
-        ts
     }
 
-    /// Generate the contents of the `parse` method
-    fn gen_parse_block(&self, res: &mut TokenStream) {
-        let (parse_path, parse_query, construct_route) =
-            self.route_contents.gen_parse_pieces(&self.ident);
+    /// Generate the attempt to parse this variant at a leaf of the parse tree.
+    ///
+    /// The parse tree has already checked the literal segments and the path length, so this only needs to parse fields.
+    /// Failures are recorded in `closest` when diagnostics are enabled, and methods of routes which matched apart from the HTTP method are collected in `allowed`.
+    fn gen_parse_block(&self, config: &Config, res: &mut TokenStream) {
+        let (parse_path, parse_query, construct_route) = self
+            .route_contents
+            .gen_parse_pieces(&self.constructor, config);
+        let lookup_query = if self.needs_query_map(config) {
+            quote! {
+                let query = &*query_map.get_or_insert_with(|| routetype::QueryMap::from_query_iter(pending_query.take()));
+            }
+        } else {
+            TokenStream::new()
+        };
+        let variant = self.ident.to_string();
+        let methods = &self.methods;
+        let check_method = if methods.is_empty() {
+            TokenStream::new()
+        } else {
+            quote! {
+                Ok(_) if matches!(request.method, Some(method) if ![#(#methods),*].contains(&method)) => {
+                    allowed.extend_from_slice(&[#(#methods),*]);
+                }
+            }
+        };
         res.append_all(quote! {
-            if let Some(route) = (|| {
-                let mut path = path.iter();
+            match (|| {
+                const VARIANT: &str = #variant;
                 #parse_path
-                if path.next().is_some() { return None; }
+                #lookup_query
                 #parse_query
-                Some(#construct_route)
+                Ok(#construct_route)
             })() {
-                return Ok(route);
+                #check_method
+                Err(routetype::RouteError::NoMatch) => (),
+                Err(routetype::RouteError::Invalid(diagnostic)) => closest.record(diagnostic),
+                Err(routetype::RouteError::MethodNotAllowed { allowed: methods }) => allowed.extend(methods),
+                res => return res,
+            }
+        })
+    }
+}
+
+/// Represents a user defined `struct` deriving `RouteQuery`, a reusable set of query string parameters.
+pub struct QueryFields {
+    /// Name of the data type
+    ident: Ident,
+    /// The query string parameters, the path is always empty
+    contents: RouteContents,
+}
+
+impl QueryFields {
+    /// Parse from user supplied input, see [Routes::parse].
+    pub fn parse(input: &DeriveInput) -> Result<Self> {
+        let data = match &input.data {
+            syn::Data::Struct(data) => data,
+            _ => bail!(
+                input.ident.span(),
+                "RouteQuery can only be derived for structs"
+            ),
+        };
+        let lit = match route_attr(&input.attrs)? {
+            Some(RouteAttr { methods, lit }) => {
+                if let Some(method) = methods.first() {
+                    bail!(method.span(), "HTTP methods are only allowed on routes");
+                }
+                lit
+            }
+            None => bail!(
+                input.ident.span(),
+                "route attribute with the query string parameters is required"
+            ),
+        };
+        if let Some(attr) = input.attrs.iter().find(|attr| is_allow_overlap(attr)) {
+            bail!(
+                attr.span(),
+                "route(allow_overlap) is only allowed on routes"
+            );
+        }
+        if let Some(attr) = input.attrs.iter().find(|attr| is_host_attr(attr)) {
+            bail!(attr.span(), "route(host) is only allowed on routes");
+        }
+        let raw_query = lit.value();
+        let raw_query = raw_query.strip_prefix('?').unwrap_or(&raw_query);
+        if raw_query.starts_with('/') {
+            bail!(
+                lit.span(),
+                "RouteQuery route attribute must only contain a query string"
+            );
+        }
+        let contents =
+            RouteContents::parse(&format!("?{}", raw_query), lit.span(), &data.fields, None)?;
+        if contents.needs_query_pairs() {
+            bail!(
+                input.ident.span(),
+                "route(query_rest) is not supported in RouteQuery"
+            );
+        }
+        Ok(QueryFields {
+            ident: input.ident.clone(),
+            contents,
+        })
+    }
+
+    /// Generate the full `impl RouteQuery` for this type.
+    ///
+    /// This reuses the query string handling of derived routes, with `variant` standing in for the `VARIANT` constant.
+    pub fn gen_impl(&self) -> TokenStream {
+        let ident = &self.ident;
+        let constructor = quote! { Self };
+        let (_, parse_query, construct) = self
+            .contents
+            .gen_parse_pieces(&constructor, &Config::default());
+        let pattern = self.contents.gen_pattern(&constructor);
+        let query_stmts = self.contents.query_arm_stmts(QuerySink::Vec);
+        let is_known_key = self.contents.gen_is_known_key(quote! { key });
+        quote! {
+            impl routetype::RouteQuery for #ident {
+                #[allow(non_snake_case, unused_variables)]
+                fn parse_route_query(
+                    query: &routetype::QueryMap<'_>,
+                    variant: &str,
+                    diagnose: bool,
+                ) -> Result<Self, routetype::RouteError> {
+                    let VARIANT = variant;
+                    #parse_query
+                    Ok(#construct)
+                }
+
+                fn render_route_query<'a>(&'a self, res: &mut Vec<routetype::QueryPair<'a>>) {
+                    let #pattern = self;
+                    #query_stmts
+                }
+
+                fn is_known_key(key: &str) -> bool {
+                    #is_known_key
+                }
             }
+        }
+    }
+}
+
+/// The contents of a `#[route(GET, POST, "...")]` attribute.
+struct RouteAttr {
+    /// HTTP methods listed before the route, not yet validated
+    methods: Vec<Ident>,
+    /// The route itself
+    lit: syn::LitStr,
+}
+
+impl syn::parse::Parse for RouteAttr {
+    fn parse(input: syn::parse::ParseStream) -> Result<Self> {
+        let mut methods = Vec::new();
+        while input.peek(Ident) {
+            methods.push(input.parse()?);
+            input.parse::<syn::Token![,]>()?;
+        }
+        Ok(RouteAttr {
+            methods,
+            lit: input.parse()?,
         })
     }
 }
 
-/// Extract the raw contents of the `#[route(...)]` attribute, if present and a string literal.
-fn raw_route_attr(attrs: &[syn::Attribute]) -> Result<String> {
+/// Extract the `#[route("...")]` attribute, if present. It must be a string literal, optionally preceded by HTTP methods.
+///
+/// `#[route(allow_overlap)]` and `#[route(host = "...")]` are separate attributes and are skipped here.
+fn route_attr(attrs: &[syn::Attribute]) -> Result<Option<RouteAttr>> {
+    for attr in attrs {
+        if attr.path.is_ident("route") && !is_allow_overlap(attr) && !is_host_attr(attr) {
+            return match attr.parse_args::<RouteAttr>() {
+                Ok(attr) => Ok(Some(attr)),
+                Err(e) => bail!(
+                    e.span(),
+                    "route attribute must be a string literal, optionally preceded by HTTP methods"
+                ),
+            };
+        }
+    }
+    Ok(None)
+}
+
+/// Check the HTTP methods from a `route` attribute. `GET` implies `HEAD`.
+fn parse_methods(idents: Vec<Ident>) -> Result<Vec<String>> {
+    let mut methods: Vec<String> = Vec::new();
+    for ident in idents {
+        let method = ident.to_string();
+        if !method.bytes().all(|b| b.is_ascii_uppercase()) {
+            bail!(
+                ident.span(),
+                "Expected an uppercase HTTP method such as GET, found {}",
+                method
+            );
+        }
+        if methods.contains(&method) {
+            bail!(
+                ident.span(),
+                "HTTP method {} is listed more than once",
+                method
+            );
+        }
+        methods.push(method);
+    }
+    if methods.iter().any(|m| m == "GET") && !methods.iter().any(|m| m == "HEAD") {
+        methods.push("HEAD".to_owned());
+    }
+    Ok(methods)
+}
+
+/// Extract the `#[route_prefix("...")]` attribute, if present. It may only contain literal path segments.
+fn route_prefix_attr(attrs: &[syn::Attribute]) -> Result<Option<String>> {
+    for attr in attrs {
+        if attr.path.is_ident("route_prefix") {
+            let lit = match attr.parse_args::<syn::LitStr>() {
+                Ok(lit) => lit,
+                Err(e) => bail!(e.span(), "route_prefix attribute must be a string literal"),
+            };
+            let prefix = lit.value();
+            if prefix.contains(['{', '}', '?']) {
+                bail!(
+                    lit.span(),
+                    "route_prefix may only contain literal path segments"
+                );
+            }
+            return Ok(Some(prefix));
+        }
+    }
+    Ok(None)
+}
+
+/// Prepend the `route_prefix` to a route. A route of `/` becomes the prefix itself, without a trailing slash.
+fn apply_prefix(prefix: Option<&str>, raw_route: &str) -> String {
+    let prefix = match prefix {
+        Some(prefix) => prefix.trim_end_matches('/'),
+        None => return raw_route.to_owned(),
+    };
+    let (path, query) = raw_route.split_at(raw_route.find('?').unwrap_or(raw_route.len()));
+    let path = path.strip_prefix('/').unwrap_or(path);
+    if path.is_empty() {
+        format!("{}{}", prefix, query)
+    } else {
+        format!("{}/{}{}", prefix, path, query)
+    }
+}
+
+/// Is this the `#[route(allow_overlap)]` attribute?
+fn is_allow_overlap(attr: &syn::Attribute) -> bool {
+    attr.path.is_ident("route")
+        && matches!(attr.parse_args::<Ident>(), Ok(ident) if ident == "allow_overlap")
+}
+
+/// Extract the `#[route(host = "...")]` attribute, if present.
+fn host_attr(attrs: &[syn::Attribute]) -> Result<Option<syn::LitStr>> {
     for attr in attrs {
-        if attr.path.is_ident("route") {
-            return Ok(attr
-                .parse_args::<syn::LitStr>()
-                .context("route attribute must be a string literal")?
-                .value());
+        if is_host_attr(attr) {
+            return match attr.parse_args::<syn::MetaNameValue>()?.lit {
+                syn::Lit::Str(lit) => Ok(Some(lit)),
+                lit => bail!(lit.span(), "route(host) must be a string literal"),
+            };
         }
     }
-    Err(anyhow!("Attribute named route not found"))
+    Ok(None)
+}
+
+/// Is this the `#[route(host = "...")]` attribute?
+fn is_host_attr(attr: &syn::Attribute) -> bool {
+    attr.path.is_ident("route")
+        && matches!(attr.parse_args::<syn::MetaNameValue>(), Ok(nv) if nv.path.is_ident("host"))
+}
+
+/// Parse out the labels of a `#[route(host = "...")]` attribute, which work like path segments without rest captures.
+///
+/// Hosts are matched case insensitively, so literal text is lowercased here.
+fn parse_host_fields<Field: AsField>(
+    lit: &syn::LitStr,
+    fields: &mut Vec<&syn::Field>,
+) -> Result<Vec<Seg<Field>>> {
+    let raw_host = lit.value();
+    let span = lit.span();
+    if raw_host.is_empty() || raw_host.contains(['/', ':', '?']) {
+        bail!(
+            span,
+            "route(host) must be a host name such as \"{{tenant}}.example.com\", without a scheme, port or path"
+        );
+    }
+    let mut counter = 0;
+    let all_fields = fields.clone();
+    raw_host
+        .split('.')
+        .map(|raw_label| {
+            let label = Seg::parse(
+                raw_label,
+                span,
+                false,
+                RouteValueType::Host,
+                &all_fields,
+                &mut counter,
+            )?;
+            label.remove_field(fields, span)?;
+            Ok(match label {
+                Seg::Single(RouteValue::Literal(s)) => {
+                    Seg::Single(RouteValue::Literal(s.to_ascii_lowercase()))
+                }
+                Seg::Affixed {
+                    field,
+                    local,
+                    prefix,
+                    suffix,
+                } => Seg::Affixed {
+                    field,
+                    local,
+                    prefix: prefix.to_ascii_lowercase(),
+                    suffix: suffix.to_ascii_lowercase(),
+                },
+                label => label,
+            })
+        })
+        .collect()
 }
 
 /// Parse out the information on the path segments.
 ///
 /// This combines the path information from the `route` attr and the fields defined on the `enum`.
+/// `span` is the location of the `route` attribute, used for error messages.
 fn parse_path_fields<Field: AsField>(
     raw_path: &str,
+    span: Span,
     fields: &mut Vec<&syn::Field>,
 ) -> Result<Vec<Seg<Field>>> {
     let raw_path = raw_path.strip_prefix('/').unwrap_or(raw_path);
@@ -229,23 +1179,42 @@ fn parse_path_fields<Field: AsField>(
         return Ok(vec![]);
     }
     let mut counter = 0;
-    raw_path
-        .split('/')
-        .map(|raw_seg| {
-            let rv = RouteValue::parse(raw_seg, RouteValueType::Path, &mut counter)?;
-            rv.remove_field(fields)?;
-            Ok(Seg(rv))
+    let all_fields = fields.clone();
+    let raw_segs: Vec<&str> = raw_path.split('/').collect();
+    raw_segs
+        .iter()
+        .enumerate()
+        .map(|(idx, raw_seg)| {
+            let last = idx + 1 == raw_segs.len();
+            let seg = Seg::parse(
+                raw_seg,
+                span,
+                last,
+                RouteValueType::Path,
+                &all_fields,
+                &mut counter,
+            )?;
+            seg.remove_field(fields, span)?;
+            Ok(seg)
         })
         .collect()
 }
 
 /// Same as `parse_path_fields` but for the query string.
+///
+/// `all_fields` are all fields of the variant, and `path_fields` is how many of them were used by the path.
 fn parse_query_fields<Field: AsField>(
     raw_query: &str,
+    span: Span,
     fields: &mut Vec<&syn::Field>,
+    all_fields: &[&syn::Field],
+    path_fields: usize,
 ) -> Result<Vec<Query<Field>>> {
     if raw_query.is_empty() {
-        bail!("Empty query string specified, please omit the question mark");
+        bail!(
+            span,
+            "Empty query string specified, please omit the question mark"
+        );
     }
     let mut counter = 0;
     raw_query
@@ -254,33 +1223,174 @@ fn parse_query_fields<Field: AsField>(
             None => Ok(Query {
                 key: raw_pair.to_owned(),
                 value: None,
+                presence: Presence::Required,
             }),
             Some(idx) => {
                 let key = raw_pair[..idx].to_owned();
                 let value = &raw_pair[idx + 1..];
-                let value = RouteValue::parse(value, RouteValueType::Query, &mut counter)?;
-                value.remove_field(fields)?;
+                let position = path_fields + counter;
+                let value: RouteValue<Field> =
+                    RouteValue::parse(value, span, RouteValueType::Query, &mut counter)?;
+                let presence = match &value {
+                    RouteValue::Literal(_) => Presence::Required,
+                    RouteValue::Field { field, .. } => {
+                        let def = field.lookup(position, all_fields, span)?;
+                        let attrs = FieldAttrs::parse(def)?;
+                        if attrs.nest {
+                            bail!(
+                                def.span(),
+                                "route(nest) is only allowed on a rest capture field, e.g. {{sub*}}"
+                            );
+                        }
+                        if let Some(separator) = attrs.separator {
+                            Presence::Separated(separator)
+                        } else if let Some(default) = attrs.default {
+                            Presence::Default(default)
+                        } else if is_generic_type(&def.ty, "Option") {
+                            Presence::Optional
+                        } else if is_generic_type(&def.ty, "Vec") {
+                            Presence::Repeated
+                        } else {
+                            Presence::Required
+                        }
+                    }
+                };
+                value.remove_field(fields, span)?;
                 Ok(Query {
                     key,
                     value: Some(value),
+                    presence,
                 })
             }
         })
         .collect()
 }
 
-/// Ensure that the provided fields are empty, raising a descriptive error message otherwise.
-fn require_fields_used(fields: Vec<&syn::Field>) -> Result<()> {
-    if fields.is_empty() {
-        Ok(())
-    } else {
-        let mut unused = Vec::new();
-        for field in &fields {
-            if let Some(ident) = field.ident.as_ref() {
-                unused.push(ident.to_string());
+/// Settings from a field's `route` attribute, e.g. `#[route(nest)]` or `#[route(default = 1)]`.
+#[derive(Default)]
+struct FieldAttrs {
+    /// The field is a nested route
+    nest: bool,
+    /// Value to use when a query string parameter is absent, `#[route(default)]` uses [Default::default]
+    default: Option<TokenStream>,
+    /// Parse a single query string value as a list with this separator, e.g. `#[route(separator = ",")]`
+    separator: Option<String>,
+    /// Capture all query string pairs not mentioned in the route
+    query_rest: bool,
+    /// The field is a `RouteQuery` parsed from the same query string
+    flatten: bool,
+    /// A function taking a reference to the parsed field, the route only matches if it returns `true`
+    validate: Option<TokenStream>,
+}
+
+impl FieldAttrs {
+    fn parse(field: &syn::Field) -> Result<Self> {
+        let mut attrs = FieldAttrs::default();
+        for attr in &field.attrs {
+            if attr.path.is_ident("route") {
+                attr.parse_args_with(|input: syn::parse::ParseStream| {
+                    while !input.is_empty() {
+                        let ident: Ident = input.parse()?;
+                        if ident == "nest" {
+                            attrs.nest = true;
+                        } else if ident == "query_rest" {
+                            attrs.query_rest = true;
+                        } else if ident == "flatten" {
+                            attrs.flatten = true;
+                        } else if ident == "default" {
+                            attrs.default = Some(if input.peek(syn::Token![=]) {
+                                input.parse::<syn::Token![=]>()?;
+                                let expr: syn::Expr = input.parse()?;
+                                quote! { #expr }
+                            } else {
+                                quote! { std::default::Default::default() }
+                            });
+                        } else if ident == "separator" {
+                            input.parse::<syn::Token![=]>()?;
+                            let separator = input.parse::<syn::LitStr>()?.value();
+                            if separator.is_empty() {
+                                return Err(input.error("separator must not be empty"));
+                            }
+                            if separator.contains('%') {
+                                return Err(input.error(
+                                    "separator must not contain %, which is used to escape it",
+                                ));
+                            }
+                            attrs.separator = Some(separator);
+                        } else if ident == "validate" {
+                            input.parse::<syn::Token![=]>()?;
+                            let func: syn::ExprPath = input.parse::<syn::LitStr>()?.parse()?;
+                            attrs.validate = Some(quote! { #func });
+                        } else {
+                            return Err(syn::Error::new(
+                                ident.span(),
+                                format!("Unknown route attribute on field: {}", ident),
+                            ));
+                        }
+                        if !input.is_empty() {
+                            input.parse::<syn::Token![,]>()?;
+                        }
+                    }
+                    Ok(())
+                })?;
             }
         }
-        Err(anyhow!("Not all fields used: {:?}", unused))
+        Ok(attrs)
+    }
+}
+
+/// Is this type syntactically a generic type with the given name, e.g. `Option`?
+fn is_generic_type(ty: &syn::Type, name: &str) -> bool {
+    match ty {
+        syn::Type::Path(syn::TypePath { qself: None, path }) => matches!(
+            path.segments.last(),
+            Some(seg) if seg.ident == name && !seg.arguments.is_empty()
+        ),
+        _ => false,
+    }
+}
+
+/// Can a field of this type never fail to parse, i.e. is it a `String` or `Cow<str>`, or an `Option` or `Vec` of those?
+///
+/// Other types may reject some values, even if only in user defined implementations, so they are assumed to be fallible.
+fn is_infallible_type(ty: &syn::Type) -> bool {
+    let seg = match ty {
+        syn::Type::Path(syn::TypePath { qself: None, path }) => match path.segments.last() {
+            Some(seg) => seg,
+            None => return false,
+        },
+        _ => return false,
+    };
+    let args: Vec<&syn::GenericArgument> = match &seg.arguments {
+        syn::PathArguments::None => vec![],
+        syn::PathArguments::AngleBracketed(args) => args.args.iter().collect(),
+        syn::PathArguments::Parenthesized(_) => return false,
+    };
+    match (seg.ident.to_string().as_str(), &args[..]) {
+        ("String", []) => true,
+        ("Cow", [syn::GenericArgument::Lifetime(_), syn::GenericArgument::Type(inner)])
+        | ("Cow", [syn::GenericArgument::Type(inner)]) => {
+            matches!(inner, syn::Type::Path(syn::TypePath { qself: None, path }) if path.is_ident("str"))
+        }
+        ("Option", [syn::GenericArgument::Type(inner)])
+        | ("Vec", [syn::GenericArgument::Type(inner)]) => is_infallible_type(inner),
+        _ => false,
+    }
+}
+
+/// Ensure that the provided fields are empty, raising an error at each unused field otherwise.
+fn require_fields_used(fields: Vec<&syn::Field>) -> Result<()> {
+    let mut res: Option<Error> = None;
+    for field in fields {
+        let e = Error::new(field.span(), "field is not used by the route");
+        match &mut res {
+            None => res = Some(e),
+            Some(res) => res.combine(e),
+        }
+    }
+    match res {
+        None => Ok(()),
+        Some(e) => Err(e),
     }
 }
 
@@ -298,26 +1408,100 @@ enum RouteContents {
 }
 
 impl RouteContents {
-    fn parse_named(raw_route: &str, fields: &syn::FieldsNamed) -> Result<Self> {
-        let fields: Vec<_> = fields.named.iter().collect();
-        Ok(Self::Named(PathAndQuery::parse(raw_route, fields)?))
+    /// `span` is the location of the `route` attribute, used for error messages. `host` is the `#[route(host = "...")]` attribute, if any.
+    fn parse(
+        raw_route: &str,
+        span: Span,
+        fields: &syn::Fields,
+        host: Option<&syn::LitStr>,
+    ) -> Result<Self> {
+        Ok(match fields {
+            syn::Fields::Named(fields) => {
+                let fields: Vec<_> = fields.named.iter().collect();
+                Self::Named(PathAndQuery::parse(raw_route, span, fields, host)?)
+            }
+            syn::Fields::Unnamed(fields) => {
+                let fields: Vec<_> = fields.unnamed.iter().collect();
+                Self::Positional(PathAndQuery::parse(raw_route, span, fields, host)?)
+            }
+            syn::Fields::Unit => Self::Unit(PathAndQuery::parse(raw_route, span, vec![], host)?),
+        })
+    }
+
+    /// parse the path, parse the query, construct the route
+    fn gen_parse_pieces(
+        &self,
+        constructor: &TokenStream,
+        config: &Config,
+    ) -> (TokenStream, TokenStream, TokenStream) {
+        match self {
+            RouteContents::Unit(pq) => pq.gen_parse_pieces(constructor, config),
+            RouteContents::Positional(pq) => pq.gen_parse_pieces(constructor, config),
+            RouteContents::Named(pq) => pq.gen_parse_pieces(constructor, config),
+        }
     }
 
-    fn parse_positional(raw_route: &str, fields: &syn::FieldsUnnamed) -> Result<Self> {
-        let fields: Vec<_> = fields.unnamed.iter().collect();
-        Ok(Self::Positional(PathAndQuery::parse(raw_route, fields)?))
+    /// See [PathAndQuery::host_expr]
+    fn host_expr(&self) -> TokenStream {
+        match self {
+            RouteContents::Unit(pq) => pq.host_expr(),
+            RouteContents::Positional(pq) => pq.host_expr(),
+            RouteContents::Named(pq) => pq.host_expr(),
+        }
     }
 
-    fn parse_unit(raw_route: &str) -> Result<Self> {
-        Ok(Self::Unit(PathAndQuery::parse(raw_route, vec![])?))
+    /// See [Route::query_arm_stmts]
+    fn query_arm_stmts(&self, sink: QuerySink) -> TokenStream {
+        let mut ts = TokenStream::new();
+        match self {
+            RouteContents::Unit(pq) => pq.query_arm_stmts(sink, &mut ts),
+            RouteContents::Positional(pq) => pq.query_arm_stmts(sink, &mut ts),
+            RouteContents::Named(pq) => pq.query_arm_stmts(sink, &mut ts),
+        }
+        ts
     }
 
-    /// parse the path, parse the query, construct the route
-    fn gen_parse_pieces(&self, ident: &Ident) -> (TokenStream, TokenStream, TokenStream) {
+    /// See [PathAndQuery::needs_query_pairs]
+    fn needs_query_pairs(&self) -> bool {
+        match self {
+            RouteContents::Unit(pq) => pq.needs_query_pairs(),
+            RouteContents::Positional(pq) => pq.needs_query_pairs(),
+            RouteContents::Named(pq) => pq.needs_query_pairs(),
+        }
+    }
+
+    /// See [PathAndQuery::needs_query_map]
+    fn needs_query_map(&self, config: &Config) -> bool {
         match self {
-            RouteContents::Unit(pq) => pq.gen_parse_pieces(ident),
-            RouteContents::Positional(pq) => pq.gen_parse_pieces(ident),
-            RouteContents::Named(pq) => pq.gen_parse_pieces(ident),
+            RouteContents::Unit(pq) => pq.needs_query_map(config),
+            RouteContents::Positional(pq) => pq.needs_query_map(config),
+            RouteContents::Named(pq) => pq.needs_query_map(config),
+        }
+    }
+
+    /// See [PathAndQuery::gen_is_known_key]
+    fn gen_is_known_key(&self, key: TokenStream) -> TokenStream {
+        match self {
+            RouteContents::Unit(pq) => pq.gen_is_known_key(key),
+            RouteContents::Positional(pq) => pq.gen_is_known_key(key),
+            RouteContents::Named(pq) => pq.gen_is_known_key(key),
+        }
+    }
+
+    /// Generate a pattern match binding all fields to their derived local names.
+    ///
+    /// This handles the differences between unit, tuple, and field syntax.
+    fn gen_pattern(&self, constructor: &TokenStream) -> TokenStream {
+        match self {
+            RouteContents::Unit(_) => quote! { #constructor },
+            RouteContents::Positional(pq) => {
+                let patterns = pq.patterns();
+                quote! { #constructor(#patterns) }
+            }
+            RouteContents::Named(pq) => {
+                let patterns = pq.patterns();
+                quote! { #constructor { #patterns } }
+            }
         }
     }
 }
@@ -327,31 +1511,238 @@ impl RouteContents {
 /// See both [RouteContents] and [AsField] for the purpose of this type parameter.
 #[derive(Debug)]
 struct PathAndQuery<Field: AsField> {
+    /// Labels of the host from `#[route(host = "...")]`, if any
+    host: Option<Vec<Seg<Field>>>,
     path: Vec<Seg<Field>>,
     query: Vec<Query<Field>>,
+    /// Fields filled in from the query string without appearing in the route, in declaration order
+    extras: Vec<Extra<Field>>,
+    /// The local name of each field with a `#[route(validate = "...")]` function, that function, and the field's type
+    validations: Vec<(Ident, TokenStream, TokenStream)>,
+    /// Some field may fail to parse or validate, used for overlap detection
+    may_fail: bool,
+}
+
+/// A field which does not appear in the route attribute, but is filled in from the query string.
+#[derive(Debug)]
+struct Extra<Field> {
+    field: Field,
+    local: Ident,
+    kind: ExtraKind,
+}
+
+/// What kind of [Extra] field this is.
+#[derive(Debug)]
+enum ExtraKind {
+    /// `#[route(query_rest)]`, capturing all query string pairs not used by the route
+    QueryRest,
+    /// `#[route(flatten)]`, a `RouteQuery` of the given type
+    Flatten(TokenStream),
 }
 
 impl<Field: AsField> PathAndQuery<Field> {
     /// Parse the complete [PathAndQuery] based on the given route attribute and fields for the variant.
-    fn parse(raw_route: &str, mut fields: Vec<&syn::Field>) -> Result<Self> {
-        Ok(match raw_route.find('?') {
+    ///
+    /// `span` is the location of the `route` attribute, used for error messages.
+    fn parse(
+        raw_route: &str,
+        span: Span,
+        mut fields: Vec<&syn::Field>,
+        host: Option<&syn::LitStr>,
+    ) -> Result<Self> {
+        let attrs = fields
+            .iter()
+            .map(|field| FieldAttrs::parse(field))
+            .collect::<Result<Vec<_>>>()?;
+        let extra_count = attrs
+            .iter()
+            .filter(|attrs| attrs.query_rest || attrs.flatten)
+            .count();
+        let first_extra = attrs.len() - extra_count;
+        let mut extras = Vec::new();
+        // Remove from the back so that the remaining indices stay valid
+        for (idx, attrs) in attrs.iter().enumerate().rev() {
+            let def = fields[idx];
+            let kind = match (attrs.query_rest, attrs.flatten) {
+                (false, false) => continue,
+                (true, false) => ExtraKind::QueryRest,
+                (false, true) => {
+                    let ty = &def.ty;
+                    ExtraKind::Flatten(quote! { #ty })
+                }
+                (true, true) => bail!(
+                    def.span(),
+                    "route(query_rest) and route(flatten) cannot be combined"
+                ),
+            };
+            if attrs.nest
+                || attrs.default.is_some()
+                || attrs.separator.is_some()
+                || attrs.validate.is_some()
+            {
+                bail!(
+                    def.span(),
+                    "route(query_rest) and route(flatten) fields cannot have other route settings"
+                );
+            }
+            fields.remove(idx);
+            let field = match &def.ident {
+                Some(ident) => Field::from_named(ident.clone(), def.span())?,
+                // Positional fields are constructed in order, so these must come after all fields used by the route
+                None if idx >= first_extra => Field::from_positional(def.span())?,
+                None => bail!(
+                    def.span(),
+                    "route(query_rest) and route(flatten) fields must come last in a tuple variant"
+                ),
+            };
+            extras.push((
+                def,
+                Extra {
+                    field,
+                    local: format_ident!("_route_value_extra_{}", idx),
+                    kind,
+                },
+            ));
+        }
+        extras.reverse();
+        let query_rests: Vec<_> = extras
+            .iter()
+            .filter(|(_, extra)| matches!(extra.kind, ExtraKind::QueryRest))
+            .collect();
+        if let Some((def, _)) = query_rests.get(1) {
+            bail!(
+                def.span(),
+                "Only one route(query_rest) field is allowed per variant"
+            );
+        }
+        let extras: Vec<_> = extras.into_iter().map(|(_, extra)| extra).collect();
+
+        let all_fields = fields.clone();
+        // Host fields come first, so that positional fields are in the order of the URL
+        let host = match host {
+            None => None,
+            Some(lit) => Some(parse_host_fields(lit, &mut fields)?),
+        };
+        let host_fields = host.iter().flatten().filter_map(Seg::field).count();
+        let mut pq = match raw_route.find('?') {
             None => {
-                let path = parse_path_fields(raw_route, &mut fields)?;
+                let path = parse_path_fields(raw_route, span, &mut fields)?;
                 require_fields_used(fields)?;
                 PathAndQuery {
+                    host,
                     path,
                     query: vec![],
+                    extras,
+                    validations: vec![],
+                    may_fail: false,
                 }
             }
             Some(idx) => {
                 let raw_path = &raw_route[..idx];
                 let raw_query = &raw_route[idx + 1..];
-                let path = parse_path_fields(raw_path, &mut fields)?;
-                let query = parse_query_fields(raw_query, &mut fields)?;
+                let path = parse_path_fields(raw_path, span, &mut fields)?;
+                let path_fields = path
+                    .iter()
+                    .filter(|seg| !matches!(seg.shape(), SegShape::Literal(_)))
+                    .count();
+                let query = parse_query_fields(
+                    raw_query,
+                    span,
+                    &mut fields,
+                    &all_fields,
+                    host_fields + path_fields,
+                )?;
                 require_fields_used(fields)?;
-                PathAndQuery { path, query }
+                PathAndQuery {
+                    host,
+                    path,
+                    query,
+                    extras,
+                    validations: vec![],
+                    may_fail: false,
+                }
+            }
+        };
+        if pq.has_query_rest() && pq.needs_nested_query() {
+            bail!(
+                span,
+                "route(query_rest) cannot be combined with route(nest), the nested route receives the full query string"
+            );
+        }
+        // Fields are numbered in order through the host, the path and then the query string
+        let locals = pq
+            .host
+            .iter()
+            .flatten()
+            .filter_map(Seg::field)
+            .chain(pq.path.iter().filter_map(Seg::field))
+            .chain(pq.query.iter().filter_map(Query::field));
+        for (position, (field, local)) in locals.enumerate() {
+            match field.find(position, &all_fields) {
+                Some(def) => {
+                    let attrs = FieldAttrs::parse(def)?;
+                    if attrs.nest || !is_infallible_type(&def.ty) {
+                        pq.may_fail = true;
+                    }
+                    if let Some(func) = attrs.validate {
+                        let ty = &def.ty;
+                        pq.validations.push((local.clone(), func, quote! { #ty }));
+                        pq.may_fail = true;
+                    }
+                }
+                None => pq.may_fail = true,
+            }
+        }
+        Ok(pq)
+    }
+
+    /// Generate the statements for the `query` method, including the query string of a nested route and any extra fields.
+    ///
+    /// `query_rest` pairs always come last.
+    fn query_arm_stmts(&self, sink: QuerySink, ts: &mut TokenStream) {
+        self.query.iter().for_each(|query| query.stmts(sink, ts));
+        self.path
+            .iter()
+            .for_each(|seg| seg.query_arm_stmts(sink, ts));
+        for Extra { local, kind, .. } in &self.extras {
+            if let ExtraKind::Flatten(_) = kind {
+                ts.append_all(match sink {
+                    QuerySink::Vec => quote! {
+                        routetype::RouteQuery::render_route_query(#local, &mut res);
+                    },
+                    QuerySink::Writer => quote! {
+                        let mut pairs = Vec::new();
+                        routetype::RouteQuery::render_route_query(#local, &mut pairs);
+                        for (key, value) in &pairs {
+                            writer.pair(key, value.as_deref())?;
+                        }
+                    },
+                });
+            }
+        }
+        for Extra { local, kind, .. } in &self.extras {
+            if let ExtraKind::QueryRest = kind {
+                let emit = sink.emit(
+                    quote! { key.as_str() },
+                    quote! { value.as_deref().map(std::borrow::Cow::Borrowed) },
+                );
+                ts.append_all(quote! {
+                    for (key, value) in #local.iter() {
+                        #emit
+                    }
+                });
             }
-        })
+        }
+    }
+
+    /// Generate the statements writing the path for the `render_path_to` method, returning an expression for whether it ends with a non-empty nested route.
+    fn render_stmts(&self, ts: &mut TokenStream) -> TokenStream {
+        self.path.iter().for_each(|seg| seg.render_stmts(ts));
+        if self.needs_nested_query() {
+            quote! { writer.segments() > nested_start }
+        } else {
+            quote! { false }
+        }
     }
 
     /// Generate the comma-separated contents of a pattern match for this route.
@@ -359,37 +1750,307 @@ impl<Field: AsField> PathAndQuery<Field> {
     /// Note that tuple and record variants will need to wrap this up with parens or braces, respectively.
     fn patterns(&self) -> TokenStream {
         let mut res = TokenStream::new();
+        self.host
+            .iter()
+            .flatten()
+            .for_each(|label| label.gen_pattern(&mut res));
         self.path.iter().for_each(|seg| seg.gen_pattern(&mut res));
         self.query
             .iter()
             .for_each(|query| query.gen_pattern(&mut res));
+        self.extras
+            .iter()
+            .for_each(|extra| extra.field.gen_pattern(&extra.local, &mut res));
         res
     }
 
-    /// parse the path, parse the query, construct the route
-    fn gen_parse_pieces(&self, ident: &Ident) -> (TokenStream, TokenStream, TokenStream) {
-        let mut parse_path = TokenStream::new();
+    /// Does parsing need the original query string pairs, for a nested route or a `query_rest` field?
+    fn needs_query_pairs(&self) -> bool {
+        self.has_query_rest() || self.needs_nested_query()
+    }
+
+    /// Does parsing look up query string parameters in the `QueryMap`, for query fields, `flatten` or `deny_unknown_query`?
+    fn needs_query_map(&self, config: &Config) -> bool {
+        !self.query.is_empty()
+            || self
+                .extras
+                .iter()
+                .any(|extra| matches!(extra.kind, ExtraKind::Flatten(_)))
+            || (config.deny_unknown_query && !self.needs_query_pairs())
+    }
+
+    /// Does this route embed another route, which receives the query string?
+    fn needs_nested_query(&self) -> bool {
         self.path
             .iter()
-            .for_each(|seg| seg.gen_parse(&mut parse_path));
+            .any(|seg| matches!(seg, Seg::Rest { nest: true, .. }))
+    }
 
-        let mut parse_query = TokenStream::new();
-        self.query
+    /// See [Route::query_shape]
+    fn query_shape(&self) -> QueryShape<'_> {
+        QueryShape {
+            required: self
+                .query
+                .iter()
+                .filter_map(|query| {
+                    let value = match (&query.value, &query.presence) {
+                        (None, _) => None,
+                        (Some(RouteValue::Literal(s)), _) => Some(SegShape::Literal(s)),
+                        (Some(RouteValue::Field { .. }), Presence::Required) => {
+                            Some(SegShape::Field)
+                        }
+                        // Absent keys are allowed
+                        (Some(RouteValue::Field { .. }), _) => return None,
+                    };
+                    Some((query.key.as_str(), value))
+                })
+                .collect(),
+            keys: self.query.iter().map(|query| query.key.as_str()).collect(),
+            open: self.needs_query_pairs(),
+            flatten: self
+                .extras
+                .iter()
+                .any(|extra| matches!(extra.kind, ExtraKind::Flatten(_))),
+        }
+    }
+
+    /// See [Route::host_shape]
+    fn host_shape(&self) -> Option<Vec<SegShape<'_>>> {
+        self.host
+            .as_ref()
+            .map(|host| host.iter().map(Seg::shape).collect())
+    }
+
+    /// Generate an `Option<String>` expression with the rendered host, for the `host` method.
+    ///
+    /// The host is lowercased, since that's how it is compared when parsing.
+    fn host_expr(&self) -> TokenStream {
+        let host = match &self.host {
+            None => return quote! { None },
+            Some(host) => host,
+        };
+        let labels = host.iter().map(|label| match label {
+            Seg::Single(RouteValue::Literal(s)) => quote! { std::borrow::Cow::Borrowed(#s) },
+            Seg::Single(RouteValue::Field { local, .. }) => {
+                quote! { routetype::RoutePiece::render_route_piece(&*#local) }
+            }
+            Seg::Affixed {
+                local,
+                prefix,
+                suffix,
+                ..
+            } => quote! {
+                std::borrow::Cow::Owned(format!(
+                    "{}{}{}",
+                    #prefix,
+                    routetype::RoutePiece::render_route_piece(&*#local),
+                    #suffix
+                ))
+            },
+            Seg::Rest { .. } => unreachable!("Rest captures are rejected in hosts"),
+        });
+        quote! {
+            Some([#(#labels),*].join(".").to_ascii_lowercase())
+        }
+    }
+
+    /// Generate the checks of the request's host, if this route is restricted to one.
+    ///
+    /// A host which doesn't fit, including a field which fails to parse, means the route doesn't match.
+    /// Like methods, a host made up of literals isn't checked when the request has no host, but fields can't be parsed without one.
+    fn gen_parse_host(&self, ts: &mut TokenStream) {
+        let host = match &self.host {
+            None => return,
+            Some(host) => host,
+        };
+        let len = host.len();
+        let mut checks = TokenStream::new();
+        checks.append_all(quote! {
+            if host.len() != #len {
+                return Err(routetype::RouteError::NoMatch);
+            }
+        });
+        for (idx, label) in host.iter().enumerate() {
+            match label {
+                Seg::Single(RouteValue::Literal(s)) => checks.append_all(quote! {
+                    if host[#idx] != #s {
+                        return Err(routetype::RouteError::NoMatch);
+                    }
+                }),
+                Seg::Single(RouteValue::Field { local, .. }) => checks.append_all(quote! {
+                    let #local = match routetype::RoutePiece::parse_route_piece(host[#idx]) {
+                        Some(value) => value,
+                        None => return Err(routetype::RouteError::NoMatch),
+                    };
+                }),
+                Seg::Affixed {
+                    local,
+                    prefix,
+                    suffix,
+                    ..
+                } => checks.append_all(quote! {
+                    let #local = match NORMALIZATION
+                        .strip_affixes(host[#idx], #prefix, #suffix)
+                        .and_then(routetype::RoutePiece::parse_route_piece)
+                    {
+                        Some(value) => value,
+                        _ => return Err(routetype::RouteError::NoMatch),
+                    };
+                }),
+                Seg::Rest { .. } => unreachable!("Rest captures are rejected in hosts"),
+            }
+            if let Some((_, local)) = label.field() {
+                self.gen_validate(local, &mut checks);
+            }
+        }
+        if host.iter().any(|label| label.field().is_some()) {
+            ts.append_all(quote! {
+                let host = match &host {
+                    Some(host) => host,
+                    None => return Err(routetype::RouteError::NoMatch),
+                };
+                #checks
+            });
+        } else {
+            ts.append_all(quote! {
+                if let Some(host) = &host {
+                    #checks
+                }
+            });
+        }
+    }
+
+    fn has_query_rest(&self) -> bool {
+        self.extras
             .iter()
-            .for_each(|query| query.gen_parse(&mut parse_query));
+            .any(|extra| matches!(extra.kind, ExtraKind::QueryRest))
+    }
+
+    /// Generate an expression checking whether the `&str` expression `key` is a query string key used by this route, including by flattened fields.
+    fn gen_is_known_key(&self, key: TokenStream) -> TokenStream {
+        let keys = self.query.iter().map(|query| &query.key);
+        let flattened = self.extras.iter().filter_map(|extra| match &extra.kind {
+            ExtraKind::Flatten(ty) => Some(ty),
+            ExtraKind::QueryRest => None,
+        });
+        quote! {
+            ([#(#keys),*].contains(&#key) #(|| <#flattened as routetype::RouteQuery>::is_known_key(#key))*)
+        }
+    }
+
+    /// Generate the check for a `#[route(validate = "...")]` function on the field with this local name, if any.
+    ///
+    /// A failed check means the route doesn't match, so the next variant is tried.
+    fn gen_validate(&self, local: &Ident, ts: &mut TokenStream) {
+        for (other, func, ty) in &self.validations {
+            if other == local {
+                // Fix the type here, so that the function's argument can use deref coercion, e.g. `&str` for a `String`
+                ts.append_all(quote! {
+                    let #local: #ty = #local;
+                    if !#func(&#local) {
+                        return Err(routetype::RouteError::NoMatch);
+                    }
+                });
+            }
+        }
+    }
+
+    /// parse the path, parse the query, construct the route
+    fn gen_parse_pieces(
+        &self,
+        constructor: &TokenStream,
+        config: &Config,
+    ) -> (TokenStream, TokenStream, TokenStream) {
+        let mut parse_path = TokenStream::new();
+        self.gen_parse_host(&mut parse_path);
+        for (idx, seg) in self.path.iter().enumerate() {
+            seg.gen_parse(idx, &mut parse_path);
+            if let Some((_, local)) = seg.field() {
+                self.gen_validate(local, &mut parse_path);
+            }
+        }
+
+        let mut parse_query = TokenStream::new();
+        for query in &self.query {
+            query.gen_parse(&mut parse_query);
+            if let Some((_, local)) = query.field() {
+                self.gen_validate(local, &mut parse_query);
+            }
+        }
+        for Extra { local, kind, .. } in &self.extras {
+            if let ExtraKind::Flatten(_) = kind {
+                parse_query.append_all(quote! {
+                    let #local = routetype::RouteQuery::parse_route_query(&query, VARIANT, diagnose)?;
+                });
+            }
+        }
+        for Extra { local, kind, .. } in &self.extras {
+            if let ExtraKind::QueryRest = kind {
+                let is_known = self.gen_is_known_key(quote! { &**key });
+                parse_query.append_all(quote! {
+                    let #local = query_pairs
+                        .iter()
+                        .flatten()
+                        .filter(|(key, _)| !#is_known)
+                        .map(|(key, value)| (key.to_string(), value.as_ref().map(|value| value.to_string())))
+                        .collect();
+                });
+            }
+        }
+        if config.deny_unknown_query && !self.needs_query_pairs() {
+            let is_known = self.gen_is_known_key(quote! { *key });
+            parse_query.append_all(quote! {
+                if let Some(key) = query.keys().find(|key| !#is_known) {
+                    return Err(routetype::RouteError::invalid(diagnose, || {
+                        routetype::diagnostic::Diagnostic::query_value(VARIANT, key, None)
+                            .with_error("unknown parameter")
+                    }));
+                }
+            });
+        }
 
         let mut construct = TokenStream::new();
+        self.host
+            .iter()
+            .flatten()
+            .for_each(|label| label.construct(&mut construct));
         self.path
             .iter()
             .for_each(|seg| seg.construct(&mut construct));
         self.query
             .iter()
             .for_each(|query| query.construct(&mut construct));
-        let construct_route = Field::wrap_construct(ident, &construct);
+        self.extras
+            .iter()
+            .for_each(|extra| extra.field.construct(&extra.local, &mut construct));
+        let construct_route = Field::wrap_construct(constructor, &construct);
         (parse_path, parse_query, construct_route)
     }
 }
 
+/// Where generated code emits query string pairs.
+#[derive(Clone, Copy)]
+enum QuerySink {
+    /// Push onto the `Vec` named `res`, for the `query` method
+    Vec,
+    /// Write to the `UrlWriter` named `writer`, for the `render_query_to` method
+    Writer,
+}
+
+impl QuerySink {
+    /// Generate a statement emitting a pair, given a `&str` expression for the key and an `Option<Cow<str>>` expression for the value.
+    fn emit(self, key: TokenStream, value: TokenStream) -> TokenStream {
+        match self {
+            QuerySink::Vec => quote! {
+                res.push((std::borrow::Cow::Borrowed(#key), #value));
+            },
+            QuerySink::Writer => quote! {
+                writer.pair(#key, Option::<std::borrow::Cow<'_, str>>::as_deref(&#value))?;
+            },
+        }
+    }
+}
+
 /// A single value within either a path segment or a query string parameter
 #[derive(Debug)]
 enum RouteValue<Field> {
@@ -402,6 +2063,7 @@ enum RouteValue<Field> {
 /// Where a route value comes from, used for nicer error messages and generated identifiers.
 #[derive(Clone, Copy)]
 enum RouteValueType {
+    Host,
     Path,
     Query,
 }
@@ -413,6 +2075,7 @@ impl RouteValueType {
         format_ident!(
             "_route_value_{}_{}",
             match self {
+                RouteValueType::Host => "host",
                 RouteValueType::Path => "path",
                 RouteValueType::Query => "query",
             },
@@ -426,26 +2089,81 @@ enum RouteValueRaw {
     Literal(String),
     Positional,
     Named(Ident),
+    /// Capture all remaining path segments, e.g. `{*}`
+    PositionalRest,
+    /// Capture all remaining path segments, e.g. `{path*}`
+    NamedRest(Ident),
 }
 
-impl FromStr for RouteValueRaw {
-    type Err = Error;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
+impl RouteValueRaw {
+    /// Parse a single path segment or query string value, reporting errors at `span`.
+    fn parse(s: &str, span: Span) -> Result<Self> {
         if s == "{}" {
             Ok(RouteValueRaw::Positional)
+        } else if s == "{*}" {
+            Ok(RouteValueRaw::PositionalRest)
         } else {
             match s.strip_prefix('{') {
+                None if split_affixes(s, span)?.is_some() => bail!(
+                    span,
+                    "Literal text around a field is only allowed in path segments, in {:?}",
+                    s
+                ),
+                None if s.contains(['{', '}']) => bail!(span, "Unbalanced braces in {:?}", s),
                 None => Ok(RouteValueRaw::Literal(s.to_owned())),
                 Some(s1) => match s1.strip_suffix('}') {
-                    Some(s2) => Ok(RouteValueRaw::Named(format_ident!("{}", s2))),
-                    None => Err(anyhow!("Invalid route value {:?}", s)),
+                    Some(s2) => match s2.strip_suffix('*') {
+                        Some(s3) => Ok(RouteValueRaw::NamedRest(parse_field_name(s3, span)?)),
+                        None => Ok(RouteValueRaw::Named(parse_field_name(s2, span)?)),
+                    },
+                    None => bail!(span, "Unbalanced braces in {:?}", s),
                 },
             }
         }
     }
 }
 
+/// Split a segment like `v{version}` or `{name}.json` into its prefix, field and suffix.
+///
+/// Returns `None` for a plain literal or a segment which is entirely a field.
+fn split_affixes(s: &str, span: Span) -> Result<Option<(&str, &str, &str)>> {
+    let start = match s.find('{') {
+        Some(start) => start,
+        None => return Ok(None),
+    };
+    let end = match s[start..].find('}') {
+        Some(end) => start + end + 1,
+        None => bail!(span, "Unbalanced braces in {:?}", s),
+    };
+    let (prefix, inner, suffix) = (&s[..start], &s[start..end], &s[end..]);
+    if prefix.contains('}') || inner[1..].contains('{') {
+        bail!(span, "Unbalanced braces in {:?}", s);
+    }
+    if suffix.contains('{') {
+        bail!(
+            span,
+            "Only one field is allowed in each path segment, in {:?}",
+            s
+        );
+    }
+    if suffix.contains('}') {
+        bail!(span, "Unbalanced braces in {:?}", s);
+    }
+    if prefix.is_empty() && suffix.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some((prefix, inner, suffix)))
+    }
+}
+
+/// Parse the field name inside `{...}`, with the span of the `route` attribute.
+fn parse_field_name(s: &str, span: Span) -> Result<Ident> {
+    let mut ident = syn::parse_str::<Ident>(s)
+        .map_err(|_| Error::new(span, format!("Invalid field name {:?}", s)))?;
+    ident.set_span(span);
+    Ok(ident)
+}
+
 /// Types which can be treated as parameterized fields in a route.
 ///
 /// This generalization allows us to handle unit, tuple, and named field variants in a type safe way. The alternative would be to use tricks like `Option<Ident>` and runtime checking.
@@ -454,11 +2172,11 @@ impl FromStr for RouteValueRaw {
 ///
 /// See [PathAndQuery] for more information.
 trait AsField: Sized {
-    /// Produce a value from a positional field, if allowed.
-    fn from_positional() -> Result<Self>;
+    /// Produce a value from a positional field, if allowed. Errors are reported at `span`.
+    fn from_positional(span: Span) -> Result<Self>;
 
-    /// Produce a value from a named field, if allowed.
-    fn from_named(ident: Ident) -> Result<Self>;
+    /// Produce a value from a named field, if allowed. Errors are reported at `span`.
+    fn from_named(ident: Ident, span: Span) -> Result<Self>;
 
     /// Generate the necessary construction code for this one field
     fn construct(&self, local: &Ident, ts: &mut TokenStream);
@@ -466,8 +2184,24 @@ trait AsField: Sized {
     /// Generate pattern matching for this one field
     fn gen_pattern(&self, local: &Ident, ts: &mut TokenStream);
 
-    /// Wrap up all of the constructed fields with appropriate wrapping for the given [Ident].
-    fn wrap_construct(ident: &Ident, contents: &TokenStream) -> TokenStream;
+    /// Wrap up all of the constructed fields with appropriate wrapping for the given constructor, e.g. `Self::Home`.
+    fn wrap_construct(constructor: &TokenStream, contents: &TokenStream) -> TokenStream;
+
+    /// Find the definition of this field, which is the `position`th interpolation in the route.
+    fn find<'a>(&self, position: usize, fields: &[&'a syn::Field]) -> Option<&'a syn::Field>;
+
+    /// Same as [AsField::find], but report a missing field as an error at `span`.
+    fn lookup<'a>(
+        &self,
+        position: usize,
+        fields: &[&'a syn::Field],
+        span: Span,
+    ) -> Result<&'a syn::Field>;
+
+    /// Remove this field from the fields not yet used by the route, so that we can later detect unused fields.
+    ///
+    /// Fields are used in order, so positional fields always remove the first remaining field.
+    fn remove_from(&self, fields: &mut Vec<&syn::Field>, span: Span) -> Result<()>;
 }
 
 /// Demonstrate the fact that some code can never be called.
@@ -476,56 +2210,104 @@ fn absurd<T>(_: Infallible) -> T {
 }
 
 impl AsField for Infallible {
-    fn from_positional() -> Result<Self> {
-        Err(anyhow!("Unit variants may not have any interpolations"))
+    fn from_positional(span: Span) -> Result<Self> {
+        bail!(span, "Unit variants may not have any interpolations")
     }
 
-    fn from_named(_ident: Ident) -> Result<Self> {
-        Err(anyhow!("Unit variants may not have any interpolations"))
+    fn from_named(_ident: Ident, span: Span) -> Result<Self> {
+        bail!(span, "Unit variants may not have any interpolations")
     }
 
     fn construct(&self, _local: &Ident, _ts: &mut TokenStream) {
         absurd(*self)
     }
 
-    fn wrap_construct(ident: &Ident, contents: &TokenStream) -> TokenStream {
+    fn wrap_construct(constructor: &TokenStream, contents: &TokenStream) -> TokenStream {
         assert!(contents.is_empty());
-        quote! { Self::#ident }
+        quote! { #constructor }
     }
 
     fn gen_pattern(&self, _local: &Ident, _ts: &mut TokenStream) {
         absurd(*self)
     }
+
+    fn find<'a>(&self, _position: usize, _fields: &[&'a syn::Field]) -> Option<&'a syn::Field> {
+        absurd(*self)
+    }
+
+    fn lookup<'a>(
+        &self,
+        _position: usize,
+        _fields: &[&'a syn::Field],
+        _span: Span,
+    ) -> Result<&'a syn::Field> {
+        absurd(*self)
+    }
+
+    fn remove_from(&self, _fields: &mut Vec<&syn::Field>, _span: Span) -> Result<()> {
+        absurd(*self)
+    }
 }
 
 impl AsField for () {
-    fn from_positional() -> Result<Self> {
+    fn from_positional(_span: Span) -> Result<Self> {
         Ok(())
     }
 
-    fn from_named(_ident: Ident) -> Result<Self> {
-        Err(anyhow!("Cannot have named field for tuple variant"))
+    fn from_named(ident: Ident, span: Span) -> Result<Self> {
+        bail!(
+            span,
+            "Cannot have named field {{{}}} for tuple variant, use {{}} instead",
+            ident
+        )
     }
 
     fn construct(&self, local: &Ident, ts: &mut TokenStream) {
         ts.append_all(quote! { #local, });
     }
 
-    fn wrap_construct(ident: &Ident, contents: &TokenStream) -> TokenStream {
-        quote! { Self::#ident(#contents) }
+    fn wrap_construct(constructor: &TokenStream, contents: &TokenStream) -> TokenStream {
+        quote! { #constructor(#contents) }
     }
 
     fn gen_pattern(&self, local: &Ident, ts: &mut TokenStream) {
         ts.append_all(quote! { #local, })
     }
+
+    fn find<'a>(&self, position: usize, fields: &[&'a syn::Field]) -> Option<&'a syn::Field> {
+        fields.get(position).copied()
+    }
+
+    fn lookup<'a>(
+        &self,
+        position: usize,
+        fields: &[&'a syn::Field],
+        span: Span,
+    ) -> Result<&'a syn::Field> {
+        match self.find(position, fields) {
+            Some(field) => Ok(field),
+            None => bail!(span, "Too many pieces of route in positional variant"),
+        }
+    }
+
+    fn remove_from(&self, fields: &mut Vec<&syn::Field>, span: Span) -> Result<()> {
+        if fields.is_empty() {
+            bail!(span, "Too many pieces of route in positional variant")
+        }
+        fields.remove(0);
+        Ok(())
+    }
 }
 
 impl AsField for Ident {
-    fn from_positional() -> Result<Self> {
-        Err(anyhow!("Cannot have positional field for named variant"))
+    fn from_positional(span: Span) -> Result<Self> {
+        bail!(
+            span,
+            "Cannot have positional field {{}} for named variant, use the field name instead"
+        )
     }
 
-    fn from_named(ident: Ident) -> Result<Self> {
+    fn from_named(ident: Ident, _span: Span) -> Result<Self> {
         Ok(ident)
     }
 
@@ -533,90 +2315,476 @@ impl AsField for Ident {
         ts.append_all(quote! { #self: #local, })
     }
 
-    fn wrap_construct(ident: &Ident, contents: &TokenStream) -> TokenStream {
-        quote! { Self::#ident { #contents } }
+    fn wrap_construct(constructor: &TokenStream, contents: &TokenStream) -> TokenStream {
+        quote! { #constructor { #contents } }
     }
 
     fn gen_pattern(&self, local: &Ident, ts: &mut TokenStream) {
         ts.append_all(quote! { #self: #local, })
     }
+
+    fn find<'a>(&self, _position: usize, fields: &[&'a syn::Field]) -> Option<&'a syn::Field> {
+        fields
+            .iter()
+            .copied()
+            .find(|field| field.ident.as_ref() == Some(self))
+    }
+
+    fn lookup<'a>(
+        &self,
+        position: usize,
+        fields: &[&'a syn::Field],
+        span: Span,
+    ) -> Result<&'a syn::Field> {
+        match self.find(position, fields) {
+            Some(field) => Ok(field),
+            None => {
+                let name = self.to_string();
+                let suggestion = fields
+                    .iter()
+                    .filter_map(|field| field.ident.as_ref())
+                    .map(|ident| ident.to_string())
+                    .map(|other| (edit_distance(&name, &other), other))
+                    .filter(|(distance, _)| *distance <= name.len().max(3) / 3)
+                    .min();
+                match suggestion {
+                    Some((_, other)) => {
+                        bail!(span, "no field named {}, did you mean {}?", name, other)
+                    }
+                    None => bail!(span, "no field named {}", name),
+                }
+            }
+        }
+    }
+
+    fn remove_from(&self, fields: &mut Vec<&syn::Field>, span: Span) -> Result<()> {
+        match fields
+            .iter()
+            .position(|field| field.ident.as_ref() == Some(self))
+        {
+            Some(idx) => {
+                fields.remove(idx);
+                Ok(())
+            }
+            // lookup has already confirmed the field exists, so it was used earlier in the route
+            None => bail!(span, "field {} is used more than once in the route", self),
+        }
+    }
+}
+
+/// Edit distance between two strings, counting swapped adjacent characters as a single edit. Used to suggest field names.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // d[i][j] is the distance between the first i characters of a and the first j characters of b
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    d[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            d[i][j] = (d[i - 1][j - 1] + cost)
+                .min(d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
 }
 
 impl<Field: AsField> RouteValue<Field> {
     /// Parse a single route value from the given attribute contents.
-    fn parse(raw: &str, typ: RouteValueType, counter: &mut usize) -> Result<Self> {
-        let raw: RouteValueRaw = raw.parse()?;
+    fn parse(raw: &str, span: Span, typ: RouteValueType, counter: &mut usize) -> Result<Self> {
+        Self::from_raw(RouteValueRaw::parse(raw, span)?, span, typ, counter)
+    }
+
+    /// Same as [RouteValue::parse], but on already parsed attribute contents.
+    fn from_raw(
+        raw: RouteValueRaw,
+        span: Span,
+        typ: RouteValueType,
+        counter: &mut usize,
+    ) -> Result<Self> {
         let field: Field = match raw {
             RouteValueRaw::Literal(l) => return Ok(RouteValue::Literal(l)),
-            RouteValueRaw::Positional => Field::from_positional()?,
-            RouteValueRaw::Named(name) => Field::from_named(name)?,
+            RouteValueRaw::Positional => Field::from_positional(span)?,
+            RouteValueRaw::Named(name) => Field::from_named(name, span)?,
+            RouteValueRaw::PositionalRest | RouteValueRaw::NamedRest(_) => {
+                bail!(
+                    span,
+                    "Rest captures are only allowed as the final path segment"
+                )
+            }
         };
         let local = typ.next_ident(counter);
         Ok(RouteValue::Field { field, local })
     }
 
     /// Remove this [RouteValue] from the fields, so that we can later detect missing fields.
-    fn remove_field(&self, fields: &mut Vec<&syn::Field>) -> Result<()> {
+    fn remove_field(&self, fields: &mut Vec<&syn::Field>, span: Span) -> Result<()> {
         match self {
             RouteValue::Literal(_) => Ok(()),
-            RouteValue::Field { .. } => {
-                // Positional, just pop
-                if fields.pop().is_none() {
-                    Err(anyhow!("Too many pieces of route in positional variant"))
-                } else {
-                    Ok(())
-                }
-            }
+            RouteValue::Field { field, .. } => field.remove_from(fields, span),
         }
     }
 }
 
 /// A single segment of the path
 #[derive(Debug)]
-struct Seg<Field>(RouteValue<Field>);
+enum Seg<Field> {
+    /// A literal or a single [RoutePiece](routetype::RoutePiece)
+    Single(RouteValue<Field>),
+    /// A single [RoutePiece](routetype::RoutePiece) with literal text around it, e.g. `{name}.json`
+    Affixed {
+        field: Field,
+        local: Ident,
+        prefix: String,
+        suffix: String,
+    },
+    /// All remaining segments, parsed by a `RouteMultiPiece`, or by a `Route` if `nest` is set
+    Rest {
+        field: Field,
+        local: Ident,
+        nest: bool,
+    },
+}
+
+/// What the parse tree needs to know about a single segment of the path.
+#[derive(Debug, Clone, Copy)]
+enum SegShape<'a> {
+    /// Must match exactly
+    Literal(&'a str),
+    /// Matches any segment, the field itself is parsed at the leaf
+    Field,
+    /// Matches any segment with this prefix and suffix, checked at the leaf
+    Affixed { prefix: &'a str, suffix: &'a str },
+    /// Matches this and all following segments, if any
+    Rest,
+}
 
 impl<Field: AsField> Seg<Field> {
+    /// Parse a single segment from the route attribute. Rest captures are only allowed for the `last` segment.
+    ///
+    /// `fields` are all fields of the variant, used to look up `#[route(...)]` settings on each field.
+    fn parse(
+        raw: &str,
+        span: Span,
+        last: bool,
+        typ: RouteValueType,
+        fields: &[&syn::Field],
+        counter: &mut usize,
+    ) -> Result<Self> {
+        // `fields` starts with those of the path or host, so the counter tells us how many fields came before this one
+        let position = *counter;
+        let raw_seg = raw;
+        if let Some((prefix, inner, suffix)) = split_affixes(raw, span)? {
+            let raw = RouteValueRaw::parse(inner, span)?;
+            if let RouteValueRaw::PositionalRest | RouteValueRaw::NamedRest(_) = raw {
+                bail!(
+                    span,
+                    "Rest captures cannot have literal text in the same segment, in {:?}",
+                    raw_seg
+                );
+            }
+            return match RouteValue::from_raw(raw, span, typ, counter)? {
+                RouteValue::Literal(_) => unreachable!(),
+                RouteValue::Field { field, local } => {
+                    Self::check_single(&field, position, fields, span)?;
+                    Ok(Seg::Affixed {
+                        field,
+                        local,
+                        prefix: prefix.to_owned(),
+                        suffix: suffix.to_owned(),
+                    })
+                }
+            };
+        }
+        let raw = RouteValueRaw::parse(raw, span)?;
+        let field = match raw {
+            RouteValueRaw::PositionalRest if last => Field::from_positional(span)?,
+            RouteValueRaw::NamedRest(name) if last => Field::from_named(name, span)?,
+            raw => {
+                let value: RouteValue<Field> = RouteValue::from_raw(raw, span, typ, counter)?;
+                if let RouteValue::Field { field, .. } = &value {
+                    Self::check_single(field, position, fields, span)?;
+                }
+                return Ok(Seg::Single(value));
+            }
+        };
+        let def = field.lookup(position, fields, span)?;
+        let attrs = FieldAttrs::parse(def)?;
+        Self::check_path_attrs(def, &attrs)?;
+        let local = typ.next_ident(counter);
+        Ok(Seg::Rest {
+            field,
+            local,
+            nest: attrs.nest,
+        })
+    }
+
+    /// The field captured by this segment and its local name, if not a literal.
+    fn field(&self) -> Option<(&Field, &Ident)> {
+        match self {
+            Seg::Single(RouteValue::Literal(_)) => None,
+            Seg::Single(RouteValue::Field { field, local })
+            | Seg::Affixed { field, local, .. }
+            | Seg::Rest { field, local, .. } => Some((field, local)),
+        }
+    }
+
+    /// Check the settings of a field which captures a single segment.
+    fn check_single(
+        field: &Field,
+        position: usize,
+        fields: &[&syn::Field],
+        span: Span,
+    ) -> Result<()> {
+        let def = field.lookup(position, fields, span)?;
+        let attrs = FieldAttrs::parse(def)?;
+        if attrs.nest {
+            bail!(
+                def.span(),
+                "route(nest) is only allowed on a rest capture field, e.g. {{sub*}}"
+            );
+        }
+        Self::check_path_attrs(def, &attrs)
+    }
+
+    /// Reject settings which only apply to query string fields.
+    fn check_path_attrs(def: &syn::Field, attrs: &FieldAttrs) -> Result<()> {
+        if attrs.default.is_some() {
+            bail!(
+                def.span(),
+                "route(default) is only allowed on query string fields"
+            );
+        }
+        if attrs.separator.is_some() {
+            bail!(
+                def.span(),
+                "route(separator) is only allowed on query string fields"
+            );
+        }
+        Ok(())
+    }
+
+    /// See [RouteValue::remove_field]
+    fn remove_field(&self, fields: &mut Vec<&syn::Field>, span: Span) -> Result<()> {
+        match self {
+            Seg::Single(rv) => rv.remove_field(fields, span),
+            Seg::Affixed { field, .. } | Seg::Rest { field, .. } => field.remove_from(fields, span),
+        }
+    }
+
     /// Generate a statement for the `path` method to push this value
     fn path_arm_stmts(&self, ts: &mut TokenStream) {
-        match &self.0 {
-            RouteValue::Literal(s) => ts.append_all(quote! {
+        match self {
+            Seg::Single(RouteValue::Literal(s)) => ts.append_all(quote! {
                 res.push(std::borrow::Cow::Borrowed(#s));
             }),
-            RouteValue::Field { local, .. } => ts.append_all(quote! {
+            Seg::Single(RouteValue::Field { local, .. }) => ts.append_all(quote! {
                 res.push(routetype::RoutePiece::render_route_piece(&*#local));
             }),
+            Seg::Affixed {
+                local,
+                prefix,
+                suffix,
+                ..
+            } => ts.append_all(quote! {
+                res.push(std::borrow::Cow::Owned(format!(
+                    "{}{}{}",
+                    #prefix,
+                    routetype::RoutePiece::render_route_piece(&*#local),
+                    #suffix
+                )));
+            }),
+            Seg::Rest {
+                local, nest: false, ..
+            } => ts.append_all(quote! {
+                res.extend(routetype::RouteMultiPiece::render_route_multi_piece(&*#local));
+            }),
+            // The nested route has already normalized its own path, so we normalize ours first to avoid doing it twice.
+            // At its own root the nested path is empty, and our trailing slash policy applies instead.
+            Seg::Rest {
+                local, nest: true, ..
+            } => ts.append_all(quote! {
+                let nested = routetype::Route::path(&*#local);
+                if nested.is_empty() {
+                    return NORMALIZATION.normalize_render_path(res);
+                }
+                let mut res = NORMALIZATION.escape_render_path(res);
+                res.extend(nested);
+                return res;
+            }),
+        }
+    }
+
+    /// Generate statements for the `render_path_to` method writing this value, see [Seg::path_arm_stmts].
+    fn render_stmts(&self, ts: &mut TokenStream) {
+        match self {
+            Seg::Single(RouteValue::Literal(s)) => ts.append_all(quote! {
+                writer.segment(&NORMALIZATION.escape_render_segment(std::borrow::Cow::Borrowed(#s)))?;
+            }),
+            Seg::Single(RouteValue::Field { local, .. }) => ts.append_all(quote! {
+                writer.segment(&NORMALIZATION.escape_render_segment(
+                    routetype::RoutePiece::render_route_piece(&*#local),
+                ))?;
+            }),
+            Seg::Affixed {
+                local,
+                prefix,
+                suffix,
+                ..
+            } => ts.append_all(quote! {
+                writer.segment(&NORMALIZATION.escape_render_segment(std::borrow::Cow::Owned(format!(
+                    "{}{}{}",
+                    #prefix,
+                    routetype::RoutePiece::render_route_piece(&*#local),
+                    #suffix
+                ))))?;
+            }),
+            Seg::Rest {
+                local, nest: false, ..
+            } => ts.append_all(quote! {
+                for seg in routetype::RouteMultiPiece::render_route_multi_piece(&*#local) {
+                    writer.segment(&NORMALIZATION.escape_render_segment(seg))?;
+                }
+            }),
+            Seg::Rest {
+                local, nest: true, ..
+            } => ts.append_all(quote! {
+                let nested_start = writer.segments();
+                routetype::Route::render_path_to(&*#local, writer)?;
+            }),
+        }
+    }
+
+    /// Generate statements for the `query` method, only used for nested routes
+    fn query_arm_stmts(&self, sink: QuerySink, ts: &mut TokenStream) {
+        if let Seg::Rest {
+            local, nest: true, ..
+        } = self
+        {
+            ts.append_all(match sink {
+                QuerySink::Vec => quote! {
+                    if let Some(query) = routetype::Route::query(&*#local) {
+                        res.extend(query);
+                    }
+                },
+                QuerySink::Writer => quote! {
+                    routetype::Route::render_query_to(&*#local, writer)?;
+                },
+            })
         }
     }
 
     /// Generate a part of a pattern match for this field, if it's not a literal
     fn gen_pattern(&self, ts: &mut TokenStream) {
-        match &self.0 {
-            RouteValue::Literal(_) => (),
-            RouteValue::Field { field, local } => field.gen_pattern(local, ts),
+        match self {
+            Seg::Single(RouteValue::Literal(_)) => (),
+            Seg::Single(RouteValue::Field { field, local })
+            | Seg::Affixed { field, local, .. }
+            | Seg::Rest { field, local, .. } => field.gen_pattern(local, ts),
         }
     }
 
-    /// Generate parse code for this segmentj
-    fn gen_parse(&self, ts: &mut TokenStream) {
-        ts.append_all(match &self.0 {
-            RouteValue::Literal(s) => {
-                quote! {
-                    if path.next()? != #s { return None }
-                }
-            }
-            RouteValue::Field { local, .. } => {
-                quote! {
-                    let #local = routetype::RoutePiece::parse_route_piece(path.next()?)?;
-                }
-            }
-        })
+    /// The shape of this segment for the parse tree
+    fn shape(&self) -> SegShape<'_> {
+        match self {
+            Seg::Single(RouteValue::Literal(s)) => SegShape::Literal(s),
+            Seg::Single(RouteValue::Field { .. }) => SegShape::Field,
+            Seg::Affixed { prefix, suffix, .. } => SegShape::Affixed { prefix, suffix },
+            Seg::Rest { .. } => SegShape::Rest,
+        }
+    }
+
+    /// Generate parse code for this segment, found at index `idx` of the path.
+    ///
+    /// Literals have already been matched by the parse tree, so only fields generate any code.
+    fn gen_parse(&self, idx: usize, ts: &mut TokenStream) {
+        match self {
+            Seg::Single(RouteValue::Literal(_)) => (),
+            Seg::Single(RouteValue::Field { local, .. }) => ts.append_all(quote! {
+                let #local = match routetype::RoutePiece::parse_route_piece_diagnostic(&path[#idx]) {
+                    Ok(value) => value,
+                    Err(e) => return Err(routetype::RouteError::invalid(diagnose, || {
+                        routetype::diagnostic::Diagnostic {
+                            error: e,
+                            ..routetype::diagnostic::Diagnostic::path_segment(VARIANT, #idx, &path[#idx])
+                        }
+                    })),
+                };
+            }),
+            // A segment without the literal text doesn't match at all, just like a literal segment
+            Seg::Affixed {
+                local,
+                prefix,
+                suffix,
+                ..
+            } => ts.append_all(quote! {
+                let #local = match NORMALIZATION.strip_affixes(&path[#idx], #prefix, #suffix) {
+                    None => return Err(routetype::RouteError::NoMatch),
+                    Some(piece) => match routetype::RoutePiece::parse_route_piece_diagnostic(piece) {
+                        Ok(value) => value,
+                        Err(e) => return Err(routetype::RouteError::invalid(diagnose, || {
+                            routetype::diagnostic::Diagnostic {
+                                error: e,
+                                ..routetype::diagnostic::Diagnostic::path_segment(VARIANT, #idx, &path[#idx])
+                            }
+                        })),
+                    },
+                };
+            }),
+            Seg::Rest {
+                local, nest: false, ..
+            } => ts.append_all(quote! {
+                let #local = match routetype::RouteMultiPiece::parse_route_multi_piece_diagnostic(&path[#idx..]) {
+                    Ok(value) => value,
+                    Err(e) => return Err(routetype::RouteError::invalid(diagnose, || {
+                        routetype::diagnostic::Diagnostic {
+                            error: e,
+                            ..routetype::diagnostic::Diagnostic::path_segment(VARIANT, #idx, &path[#idx..].join("/"))
+                        }
+                    })),
+                };
+            }),
+            // Undo our normalization before handing off to the nested route, which will normalize again.
+            // This restores any required trailing slash, so the nested route sees the path as requested.
+            Seg::Rest {
+                local, nest: true, ..
+            } => ts.append_all(quote! {
+                let segments = NORMALIZATION.normalize_render_path(path[#idx..].to_vec()).into_iter();
+                let pairs = query_pairs.as_ref().map(|query| query.iter().cloned());
+                let res = if diagnose {
+                    routetype::Route::parse_request_diagnostic(request, segments, pairs)
+                } else {
+                    routetype::Route::parse_request(request, segments, pairs)
+                };
+                let #local = match res {
+                    Ok(route) => route,
+                    Err(routetype::RouteError::NoMatch) => return Err(routetype::RouteError::NoMatch),
+                    Err(routetype::RouteError::Invalid(diagnostic)) => {
+                        return Err(routetype::RouteError::Invalid(Box::new((*diagnostic).nested(VARIANT, #idx))))
+                    }
+                    Err(e) => {
+                        let prefix = NORMALIZATION.escape_render_path(path[..#idx].to_vec());
+                        return Err(e.with_prefix(prefix.iter().map(|seg| &**seg)));
+                    }
+                };
+            }),
+        }
     }
 
     /// Call [AsField::construct] if not a literal.
     fn construct(&self, ts: &mut TokenStream) {
-        match &self.0 {
-            RouteValue::Literal(_) => (),
-            RouteValue::Field { field, local } => field.construct(local, ts),
+        match self {
+            Seg::Single(RouteValue::Literal(_)) => (),
+            Seg::Single(RouteValue::Field { field, local })
+            | Seg::Affixed { field, local, .. }
+            | Seg::Rest { field, local, .. } => field.construct(local, ts),
         }
     }
 }
@@ -628,21 +2796,89 @@ struct Query<Field> {
     key: String,
     /// Value, [None] represents lack of a `=`
     value: Option<RouteValue<Field>>,
+    /// What to do if the key is absent, only relevant for fields
+    presence: Presence,
+}
+
+/// How a query string field handles an absent key.
+#[derive(Debug)]
+enum Presence {
+    /// The key must be present exactly once
+    Required,
+    /// The field is an `Option`, absent keys are `None` and `None` is not rendered
+    Optional,
+    /// Absent keys use the given default, which is not rendered
+    Default(TokenStream),
+    /// A `RouteMultiPiece` such as `Vec`, with one value per occurrence of the key
+    Repeated,
+    /// A `RouteMultiPiece`, with all values joined by the separator in a single occurrence of the key
+    Separated(String),
 }
 
 impl<Field: AsField> Query<Field> {
+    /// The field for this pair's value and its local name, if not a literal.
+    fn field(&self) -> Option<(&Field, &Ident)> {
+        match &self.value {
+            Some(RouteValue::Field { field, local }) => Some((field, local)),
+            _ => None,
+        }
+    }
+
     /// Generate the statement for the `query` method.
-    fn stmts(&self, ts: &mut TokenStream) {
+    fn stmts(&self, sink: QuerySink, ts: &mut TokenStream) {
         let key = &self.key;
+        let emit = |value: TokenStream| sink.emit(quote! { #key }, value);
         ts.append_all(match &self.value {
-            None => quote! {
-                res.push((std::borrow::Cow::Borrowed(#key), None));
-            },
-            Some(RouteValue::Literal(value)) => quote! {
-                res.push((std::borrow::Cow::Borrowed(#key), Some(std::borrow::Cow::Borrowed(#value))));
-            },
-            Some(RouteValue::Field { local, .. }) => quote! {
-                res.push((std::borrow::Cow::Borrowed(#key), Some(routetype::RoutePiece::render_route_piece(&*#local))));
+            None => emit(quote! { None }),
+            Some(RouteValue::Literal(value)) => {
+                emit(quote! { Some(std::borrow::Cow::Borrowed(#value)) })
+            }
+            Some(RouteValue::Field { local, .. }) => match &self.presence {
+                Presence::Required => {
+                    emit(quote! { Some(routetype::RoutePiece::render_route_piece(&*#local)) })
+                }
+                Presence::Optional => {
+                    let emit = emit(quote! { Some(routetype::RoutePiece::render_route_piece(value)) });
+                    quote! {
+                        if let Some(value) = #local {
+                            #emit
+                        }
+                    }
+                }
+                Presence::Default(default) => {
+                    let emit = emit(quote! { Some(routetype::RoutePiece::render_route_piece(&*#local)) });
+                    quote! {
+                        {
+                            fn is_default<T: PartialEq>(value: &T, default: T) -> bool {
+                                *value == default
+                            }
+                            if !is_default(#local, #default) {
+                                #emit
+                            }
+                        }
+                    }
+                }
+                Presence::Repeated => {
+                    let emit = emit(quote! { Some(value) });
+                    quote! {
+                        for value in routetype::RouteMultiPiece::render_route_multi_piece(&*#local) {
+                            #emit
+                        }
+                    }
+                }
+                Presence::Separated(separator) => {
+                    let emit = emit(quote! {
+                        Some(std::borrow::Cow::Owned(routetype::raw::join_separated(&values, #separator)))
+                    });
+                    quote! {
+                        {
+                            let values = routetype::RouteMultiPiece::render_route_multi_piece(&*#local);
+                            if !values.is_empty() {
+                                #emit
+                            }
+                        }
+                    }
+                }
             },
         })
     }
@@ -652,13 +2888,88 @@ impl<Field: AsField> Query<Field> {
         let key = &self.key;
         ts.append_all(match &self.value {
             None => quote! {
-                if !query.contains(#key) { return None }
+                if !query.contains(#key) {
+                    return Err(routetype::RouteError::invalid(diagnose, || {
+                        routetype::diagnostic::Diagnostic::query_value(VARIANT, #key, None)
+                    }));
+                }
             },
             Some(RouteValue::Literal(s)) => quote! {
-                if query.get_single(#key)? != #s { return None }
+                match query.get_single(#key) {
+                    Some(#s) => (),
+                    value => return Err(routetype::RouteError::invalid(diagnose, || {
+                        routetype::diagnostic::Diagnostic::query_value(VARIANT, #key, value)
+                            .with_error(format_args!("expected {:?}", #s))
+                    })),
+                }
             },
-            Some(RouteValue::Field { local, .. }) => quote! {
-                let #local = routetype::RoutePiece::parse_route_piece(query.get_single(#key)?)?;
+            Some(RouteValue::Field { local, .. }) => match &self.presence {
+                Presence::Repeated => quote! {
+                    let #local = match query.get_all(#key) {
+                        Some(values) => match routetype::RouteMultiPiece::parse_route_multi_piece_diagnostic(values) {
+                            Ok(parsed) => parsed,
+                            Err(e) => return Err(routetype::RouteError::invalid(diagnose, || {
+                                routetype::diagnostic::Diagnostic {
+                                    error: e,
+                                    ..routetype::diagnostic::Diagnostic::query_value(VARIANT, #key, Some(&values.join("&")))
+                                }
+                            })),
+                        },
+                        None => return Err(routetype::RouteError::invalid(diagnose, || {
+                            routetype::diagnostic::Diagnostic::query_value(VARIANT, #key, None)
+                                .with_error("expected a value for every occurrence")
+                        })),
+                    };
+                },
+                Presence::Separated(separator) => quote! {
+                    let #local = {
+                        let values: Vec<routetype::PathSegment> = match query.get_single(#key) {
+                            Some(value) => routetype::raw::split_separated(value, #separator),
+                            None if !query.contains(#key) => Vec::new(),
+                            None => return Err(routetype::RouteError::invalid(diagnose, || {
+                                routetype::diagnostic::Diagnostic::query_value(VARIANT, #key, None)
+                            })),
+                        };
+                        match routetype::RouteMultiPiece::parse_route_multi_piece_diagnostic(&values) {
+                            Ok(parsed) => parsed,
+                            Err(e) => return Err(routetype::RouteError::invalid(diagnose, || {
+                                routetype::diagnostic::Diagnostic {
+                                    error: e,
+                                    ..routetype::diagnostic::Diagnostic::query_value(VARIANT, #key, query.get_single(#key))
+                                }
+                            })),
+                        }
+                    };
+                },
+                presence => {
+                    let (parsed, absent) = match presence {
+                        Presence::Optional => (quote! { Some(parsed) }, Some(quote! { None })),
+                        Presence::Default(default) => (quote! { parsed }, Some(default.clone())),
+                        _ => (quote! { parsed }, None),
+                    };
+                    let absent = absent.map(|absent| {
+                        quote! {
+                            None if !query.contains(#key) => #absent,
+                        }
+                    });
+                    quote! {
+                        let #local = match query.get_single(#key) {
+                            Some(value) => match routetype::RoutePiece::parse_route_piece_diagnostic(value) {
+                                Ok(parsed) => #parsed,
+                                Err(e) => return Err(routetype::RouteError::invalid(diagnose, || {
+                                    routetype::diagnostic::Diagnostic {
+                                        error: e,
+                                        ..routetype::diagnostic::Diagnostic::query_value(VARIANT, #key, Some(value))
+                                    }
+                                })),
+                            },
+                            #absent
+                            None => return Err(routetype::RouteError::invalid(diagnose, || {
+                                routetype::diagnostic::Diagnostic::query_value(VARIANT, #key, None)
+                            })),
+                        };
+                    }
+                }
             },
         });
     }
@@ -5,11 +5,11 @@ use tokio::try_join;
 
 #[derive(Route, Clone, PartialEq, Debug)]
 enum MyRoute {
-    #[route("/")]
+    #[route(GET, "/")]
     Home,
-    #[route("css/style.css")]
+    #[route(GET, "css/style.css")]
     Style,
-    #[route("/hello/{name}")]
+    #[route(GET, "/hello/{name}")]
     Hello { name: String },
 }
 
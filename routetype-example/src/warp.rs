@@ -3,11 +3,11 @@ use std::convert::Infallible;
 
 #[derive(Route, Clone, PartialEq, Debug)]
 enum MyRoute {
-    #[route("/")]
+    #[route(GET, "/")]
     Home,
-    #[route("css/style.css")]
+    #[route(GET, "css/style.css")]
     Style,
-    #[route("/hello/{name}")]
+    #[route(GET, "/hello/{name}")]
     Hello { name: String },
 }
 
@@ -36,19 +36,23 @@ async fn get_hello(name: String) -> impl Reply {
 
 #[tokio::main]
 async fn main() {
-    let app = route_filter_result().and_then(|route| async move {
+    let app = route_filter_request_result().and_then(|method, route| async move {
         // This could be automatically derived in theory
         Ok::<_, Infallible>(match route {
             Ok(MyRoute::Home) => get_home().await.into_response(),
             Ok(MyRoute::Style) => get_style().await.into_response(),
             Ok(MyRoute::Hello { name }) => get_hello(name).await.into_response(),
-            Err(RouteError::NoMatch) => default_not_found().into_response(),
+            Err(RouteError::MethodNotAllowed { allowed }) => {
+                default_method_not_allowed(&method, &allowed).into_response()
+            }
             Err(RouteError::NormalizationFailed(dest)) => {
                 let uri: warp::http::Uri = dest
                     .parse()
                     .expect("Normalization failure contained invalid URI");
                 warp::redirect::permanent(uri).into_response()
             }
+            // NoMatch, Invalid, and any future failures
+            Err(_) => default_not_found().into_response(),
         })
     });
     serve(app).run(([127, 0, 0, 1], 3000)).await;
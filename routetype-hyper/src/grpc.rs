@@ -119,7 +119,6 @@ where
     if request.headers().get("content-type").map(|x| x.as_bytes()) == Some(b"application/grpc") {
         let res = make_grpc_service(app).call(request).await;
         res.map(|res| res.map(EitherBody::Left))
-            .map_err(Error::from)
     } else {
         let res = crate::helper(remote, app, request).await;
         res.map(|res| res.map(EitherBody::Right))
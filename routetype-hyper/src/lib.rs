@@ -29,21 +29,86 @@ pub struct DispatchInput<D: Dispatch> {
 pub trait Dispatch: Sized + Send + Sync + 'static {
     type Route: Route;
 
+    /// Parse requests with [Route::parse_diagnostic], calling [Dispatch::invalid] instead of [Dispatch::not_found] when a variant almost matched.
+    const DIAGNOSE: bool = false;
+
     async fn dispatch(input: DispatchInput<Self>, route: Self::Route) -> Result<Response<Body>>;
 
     async fn not_found(_input: DispatchInput<Self>) -> Result<Response<Body>> {
         Ok(default_not_found())
     }
 
+    /// Only called when [Dispatch::DIAGNOSE] is enabled.
+    async fn invalid(
+        _input: DispatchInput<Self>,
+        diagnostic: diagnostic::Diagnostic,
+    ) -> Result<Response<Body>> {
+        Ok(default_invalid(&diagnostic))
+    }
+
+    /// Called when a route matched the path, but not the request method. Answers `OPTIONS` requests and otherwise gives a 405.
+    async fn method_not_allowed(
+        input: DispatchInput<Self>,
+        allowed: Vec<&'static str>,
+    ) -> Result<Response<Body>> {
+        Ok(default_method_not_allowed(input.request.method(), &allowed))
+    }
+
     fn into_server(self) -> DispatchServer<Self> {
         DispatchServer(Arc::new(self))
     }
+
+    /// Hand off a nested route, e.g. `Admin(AdminRoute)`, to its [SubDispatch] implementation.
+    async fn sub_dispatch<R>(input: DispatchInput<Self>, route: R) -> Result<Response<Body>>
+    where
+        R: SubDispatch<Self>,
+    {
+        R::dispatch(input, route).await
+    }
+}
+
+/// Dispatch for a route nested within [Dispatch::Route] via `#[route(nest)]`.
+///
+/// This allows the handlers for a nested route to live in a separate module or crate from the main application.
+#[async_trait]
+pub trait SubDispatch<D: Dispatch>: Route {
+    async fn dispatch(input: DispatchInput<D>, route: Self) -> Result<Response<Body>>;
 }
 
 pub fn default_not_found() -> Response<Body> {
     respond::html("<h1>File not found</h1>")
 }
 
+pub fn default_invalid(diagnostic: &diagnostic::Diagnostic) -> Response<Body> {
+    let mut res = respond::text(format!("Bad request\n{}\n", diagnostic));
+    *res.status_mut() = hyper::StatusCode::BAD_REQUEST;
+    res
+}
+
+/// Respond to an `OPTIONS` request with `204 No Content`, or anything else with `405 Method Not Allowed`.
+///
+/// Both include an `Allow` header listing `allowed` and `OPTIONS`.
+pub fn default_method_not_allowed(method: &hyper::Method, allowed: &[&str]) -> Response<Body> {
+    let mut allow = allowed.join(", ");
+    if !allowed.contains(&"OPTIONS") {
+        allow.push_str(", OPTIONS");
+    }
+    let mut res = if method == hyper::Method::OPTIONS {
+        let mut res = Response::new(Body::empty());
+        *res.status_mut() = hyper::StatusCode::NO_CONTENT;
+        res
+    } else {
+        let mut res = respond::text("Method not allowed\n");
+        *res.status_mut() = hyper::StatusCode::METHOD_NOT_ALLOWED;
+        res
+    };
+    res.headers_mut().insert(
+        hyper::header::ALLOW,
+        HeaderValue::from_str(&allow).expect("HTTP methods are valid header values"),
+    );
+    res
+}
+
 pub trait DispatchOutput: Sized {
     fn into_response(self) -> Result<Response<Body>>;
 }
@@ -152,26 +217,52 @@ impl<T: Dispatch> Service<Request<Body>> for DispatchServerConn<T> {
     }
 }
 
+/// The host of a request, from the URI if it's in absolute form, otherwise the `Host` header.
+pub fn request_host<B>(request: &Request<B>) -> Option<&str> {
+    request
+        .uri()
+        .authority()
+        .map(|authority| authority.as_str())
+        .or_else(|| {
+            request
+                .headers()
+                .get(hyper::header::HOST)
+                .and_then(|host| host.to_str().ok())
+        })
+}
+
 pub(crate) async fn helper<T: Dispatch>(
     remote: SocketAddr,
     app: Arc<T>,
     request: Request<Body>,
 ) -> Result<Response<Body>, Infallible> {
-    let route = T::Route::parse_str(
-        request
-            .uri()
-            .path_and_query()
-            .expect("path_and_query cannot be None")
-            .as_str(),
-    );
+    let path_and_query = request
+        .uri()
+        .path_and_query()
+        .expect("path_and_query cannot be None")
+        .as_str();
+    let info = RequestInfo {
+        method: Some(request.method().as_str()),
+        host: request_host(&request),
+    };
+    let route = if T::DIAGNOSE {
+        T::Route::parse_str_request_diagnostic(info, path_and_query)
+    } else {
+        T::Route::parse_str_request(info, path_and_query)
+    };
     let input = DispatchInput {
         app,
         request,
         remote,
     };
     let output = match route {
-        Err(RouteError::NoMatch) => T::not_found(input).await,
+        Err(RouteError::Invalid(diagnostic)) => T::invalid(input, *diagnostic).await,
         Err(RouteError::NormalizationFailed(dest)) => respond::redirect::temporary(dest),
+        Err(RouteError::MethodNotAllowed { allowed }) => {
+            T::method_not_allowed(input, allowed).await
+        }
+        // NoMatch, and any future failures
+        Err(_) => T::not_found(input).await,
         Ok(route) => T::dispatch(input, route).await,
     };
     let res = match output {
@@ -17,6 +17,15 @@ pub fn askama<T: askama::Template>(t: T) -> Result<Response<Body>> {
         .context("Unable to render Askama template")
 }
 
+pub fn text<B: Into<Body>>(body: B) -> Response<Body> {
+    let mut res = hyper::Response::new(body.into());
+    res.headers_mut().append(
+        hyper::header::CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; charset=utf-8"),
+    );
+    res
+}
+
 pub fn css<B: Into<Body>>(body: B) -> Response<Body> {
     let mut res = hyper::Response::new(body.into());
     res.headers_mut().append(
@@ -44,4 +53,11 @@ pub mod redirect {
         );
         Ok(res)
     }
+
+    /// Like [temporary], but to a rendered route, e.g. a [RouteWithFragment](routetype::RouteWithFragment).
+    pub fn temporary_route<U: routetype::RenderUrl>(
+        dest: &U,
+    ) -> Result<hyper::Response<hyper::Body>> {
+        temporary(dest.render_url())
+    }
 }
@@ -1,5 +1,5 @@
 pub use async_trait::async_trait;
-pub use routetype::{Route, RouteError};
+pub use routetype::{RenderUrl, Route, RouteError, RouteWithFragment};
 use std::{convert::Infallible, sync::Arc};
 pub use warp::{serve, Filter, Reply};
 
@@ -18,7 +18,7 @@ pub fn route_filter_option<R: Route>(
     route_filter_result().and_then(|r: Result<R, RouteError>| async move {
         match r {
             Ok(r) => Ok(Some(r)),
-            Err(RouteError::NoMatch) => Ok(None),
+            Err(RouteError::NoMatch) | Err(RouteError::Invalid(_)) => Ok(None),
             Err(RouteError::NormalizationFailed(s)) => {
                 let uri: warp::http::Uri = s.parse().expect("Route parsing gave an invalid URI");
                 Err(warp::redirect::permanent(uri))
@@ -28,22 +28,77 @@ pub fn route_filter_option<R: Route>(
 }
 */
 
-/// Attempt to extract the route
+/// Attempt to extract the route, using the request's host for variants restricted to one, see [Route::parse_request].
 pub fn route_filter_result<R: Route>(
 ) -> impl Filter<Error = std::convert::Infallible, Extract = (Result<R, RouteError>,)>
        + Clone
        + Send
        + Sync
        + 'static {
+    use warp::filters::host::Authority;
+    host_filter().and(path_and_query_filter()).map(
+        |host: Option<Authority>, path_and_query: String| {
+            let info = routetype::RequestInfo {
+                method: None,
+                host: host.as_ref().map(Authority::as_str),
+            };
+            R::parse_str_request(info, &path_and_query)
+        },
+    )
+}
+
+/// Attempt to extract the route, only matching variants which accept the request method and host, see [Route::parse_request].
+///
+/// The method is extracted as well, so that `OPTIONS` requests can be told apart from other [RouteError::MethodNotAllowed] failures.
+pub fn route_filter_request_result<R: Route>() -> impl Filter<
+    Error = std::convert::Infallible,
+    Extract = (warp::http::Method, Result<R, RouteError>),
+> + Clone
+       + Send
+       + Sync
+       + 'static {
+    use warp::filters::{host::Authority, method::method};
+    method()
+        .and(host_filter())
+        .and(path_and_query_filter())
+        .map(
+            |method: warp::http::Method, host: Option<Authority>, path_and_query: String| {
+                let info = routetype::RequestInfo {
+                    method: Some(method.as_str()),
+                    host: host.as_ref().map(Authority::as_str),
+                };
+                let route = R::parse_str_request(info, &path_and_query);
+                (method, route)
+            },
+        )
+        .untuple_one()
+}
+
+/// Extract the host of the request, treating an invalid `Host` header like a missing one.
+fn host_filter(
+) -> impl Filter<Error = std::convert::Infallible, Extract = (Option<warp::filters::host::Authority>,)>
+       + Clone
+       + Send
+       + Sync
+       + 'static {
+    warp::host::optional()
+        .or(warp::any().map(|| None::<warp::filters::host::Authority>))
+        .unify()
+}
+
+/// Extract the path and query string of the request as a single string.
+fn path_and_query_filter(
+) -> impl Filter<Error = std::convert::Infallible, Extract = (String,)> + Clone + Send + Sync + 'static
+{
     use warp::filters::{
         path::{full, FullPath},
         query::raw,
     };
-    let both = raw()
+    raw()
         .and(full())
-        .map(|query: String, path: FullPath| R::parse_strs(path.as_str(), &query));
-    let just_path = full().map(|path: FullPath| R::parse_str(path.as_str()));
-    both.or(just_path).unify()
+        .map(|query: String, path: FullPath| format!("{}?{}", path.as_str(), query))
+        .or(full().map(|path: FullPath| path.as_str().to_owned()))
+        .unify()
 }
 
 #[async_trait]
@@ -55,6 +110,15 @@ pub trait Dispatch: Sized + Send + Sync + 'static {
         default_not_found().into_response()
     }
 
+    /// Called when a route matched the path, but not the request method. Answers `OPTIONS` requests and otherwise gives a 405.
+    async fn method_not_allowed(
+        self: Arc<Self>,
+        method: warp::http::Method,
+        allowed: Vec<&'static str>,
+    ) -> warp::reply::Response {
+        default_method_not_allowed(&method, &allowed).into_response()
+    }
+
     fn into_filter(self) -> warp::filters::BoxedFilter<(warp::reply::Response,)> {
         dispatch_filter(self).boxed()
     }
@@ -67,24 +131,68 @@ pub fn default_not_found() -> impl warp::Reply {
     )
 }
 
+/// Respond to an `OPTIONS` request with `204 No Content`, or anything else with `405 Method Not Allowed`.
+///
+/// Both include an `Allow` header listing `allowed` and `OPTIONS`.
+pub fn default_method_not_allowed(
+    method: &warp::http::Method,
+    allowed: &[&str],
+) -> impl warp::Reply {
+    let mut allow = allowed.join(", ");
+    if !allowed.contains(&"OPTIONS") {
+        allow.push_str(", OPTIONS");
+    }
+    let (body, status) = if method == warp::http::Method::OPTIONS {
+        ("", warp::http::StatusCode::NO_CONTENT)
+    } else {
+        (
+            "Method not allowed\n",
+            warp::http::StatusCode::METHOD_NOT_ALLOWED,
+        )
+    };
+    warp::reply::with_header(
+        warp::reply::with_status(body, status),
+        warp::http::header::ALLOW,
+        allow,
+    )
+}
+
+/// Temporary redirect to a rendered route, e.g. a [RouteWithFragment].
+///
+/// Unlike [warp::redirect::temporary], this keeps the fragment.
+pub fn redirect_temporary<U: RenderUrl>(dest: &U) -> warp::reply::Response {
+    warp::reply::with_header(
+        warp::http::StatusCode::TEMPORARY_REDIRECT,
+        warp::http::header::LOCATION,
+        dest.render_url(),
+    )
+    .into_response()
+}
+
 pub fn dispatch_filter<App: Dispatch>(
     app: App,
 ) -> impl Filter<Error = Infallible, Extract = (warp::reply::Response,)> + Clone + Send + Sync + 'static
 {
     let app = std::sync::Arc::new(app);
-    route_filter_result::<App::Route>().and_then(move |route: Result<App::Route, RouteError>| {
-        let app = app.clone();
-        async move {
-            Ok::<_, Infallible>(match route {
-                Ok(route) => app.dispatch(route).await,
-                Err(RouteError::NoMatch) => app.not_found().await,
-                Err(RouteError::NormalizationFailed(dest)) => {
-                    let uri: warp::http::Uri = dest
-                        .parse()
-                        .expect("Normalization failure contained invalid URI");
-                    warp::redirect::permanent(uri).into_response()
-                }
-            })
-        }
-    })
+    route_filter_request_result::<App::Route>().and_then(
+        move |method: warp::http::Method, route: Result<App::Route, RouteError>| {
+            let app = app.clone();
+            async move {
+                Ok::<_, Infallible>(match route {
+                    Ok(route) => app.dispatch(route).await,
+                    Err(RouteError::MethodNotAllowed { allowed }) => {
+                        app.method_not_allowed(method, allowed).await
+                    }
+                    Err(RouteError::NormalizationFailed(dest)) => {
+                        let uri: warp::http::Uri = dest
+                            .parse()
+                            .expect("Normalization failure contained invalid URI");
+                        warp::redirect::permanent(uri).into_response()
+                    }
+                    // NoMatch, Invalid, and any future failures
+                    Err(_) => app.not_found().await,
+                })
+            }
+        },
+    )
 }
@@ -0,0 +1,68 @@
+//! Parsing derived routes from a borrowed path and query string.
+//!
+//! The `parse preamble` group compares the work done before matching any variant with how derived routes did it before, collecting the path into a `Vec` and always building the `QueryMap`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use routetype::Route;
+
+// Each benchmark only uses some of the shared helpers
+#[allow(dead_code)]
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use common::{parse_preamble_inline, parse_preamble_via_vecs};
+
+#[derive(Route, Clone, PartialEq, Debug)]
+enum BenchRoute {
+    #[route("/")]
+    Home,
+    #[route("/users/{}/posts/{}")]
+    Post(u64, String),
+    #[route("/search?q={query}&page={page}")]
+    Search { query: String, page: u32 },
+    #[route("/static/{path*}")]
+    Static { path: Vec<String> },
+}
+
+/// The inputs, and whether they match a variant with query string fields, i.e. whether parsing builds a `QueryMap`.
+const INPUTS: &[(&str, &str, bool)] = &[
+    ("home", "/", false),
+    ("path", "/users/42/posts/hello-world", false),
+    (
+        "path, ignored query",
+        "/users/42/posts/hello-world?utm_source=feed&utm_medium=rss",
+        false,
+    ),
+    (
+        "path, percent encoded",
+        "/users/42/posts/hello%20world",
+        false,
+    ),
+    ("query", "/search?q=rust&page=2", true),
+    ("rest", "/static/css/site/main.css", false),
+    ("no match", "/nothing/to/see/here", false),
+];
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for (name, input, _) in INPUTS {
+        group.bench_function(*name, |b| b.iter(|| BenchRoute::parse_str(input)));
+    }
+    group.finish();
+}
+
+fn parse_preamble(c: &mut Criterion) {
+    for (name, input, uses_query) in INPUTS {
+        let mut group = c.benchmark_group(format!("parse preamble, {}", name));
+        group.bench_function("via vecs", |b| b.iter(|| parse_preamble_via_vecs(input)));
+        group.bench_function("inline", |b| {
+            b.iter(|| {
+                parse_preamble_inline(input, *uses_query).map(|(path, query)| (path.len(), query))
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, parse, parse_preamble);
+criterion_main!(benches);
@@ -0,0 +1,53 @@
+//! Compares rendering through [Route::path] and [Route::query] with the streaming [Route::render_to].
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use routetype::Route;
+use std::fmt::Write;
+
+// Each benchmark only uses some of the shared helpers
+#[allow(dead_code)]
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use common::render_via_vecs;
+
+#[derive(Route, Clone, PartialEq, Debug)]
+enum BenchRoute {
+    #[route("/")]
+    Home,
+    #[route("/users/{}/posts/{}")]
+    Post(u64, String),
+    #[route("/search?q={query}&page={page}")]
+    Search { query: String, page: u32 },
+}
+
+fn render(c: &mut Criterion) {
+    let routes = [
+        ("home", BenchRoute::Home),
+        ("path", BenchRoute::Post(42, "hello world".to_owned())),
+        (
+            "query",
+            BenchRoute::Search {
+                query: "rust routing".to_owned(),
+                page: 2,
+            },
+        ),
+    ];
+    let mut buffer = String::new();
+    for (name, route) in &routes {
+        let mut group = c.benchmark_group(*name);
+        group.bench_function("via path and query", |b| b.iter(|| render_via_vecs(route)));
+        group.bench_function("render", |b| b.iter(|| route.render()));
+        group.bench_function("Display into a buffer", |b| {
+            b.iter(|| {
+                buffer.clear();
+                write!(buffer, "{}", route).unwrap();
+                buffer.len()
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
@@ -0,0 +1,124 @@
+use super::RouteError;
+use std::fmt;
+
+/// Details on why a route failed to parse, returned by [Route::parse_diagnostic](super::Route::parse_diagnostic).
+///
+/// This describes the variant which came closest to matching, i.e. all of its literal path segments matched, but one of its fields or query string parameters did not.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Name of the closest matching variant, e.g. `Foo`, or `Admin::User` for nested routes.
+    pub variant: String,
+    /// Where in the request the failure occurred.
+    pub location: DiagnosticLocation,
+    /// The value which failed to parse, or `None` if it was missing.
+    pub value: Option<String>,
+    /// A description of the failure, if available.
+    pub error: Option<String>,
+}
+
+/// Where in the request a [Diagnostic] occurred.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiagnosticLocation {
+    /// The path segment at the given index, after normalization.
+    PathSegment(usize),
+    /// The query string parameter with the given key.
+    QueryKey(String),
+}
+
+impl Diagnostic {
+    /// A path segment failed to parse.
+    pub fn path_segment(variant: &str, index: usize, value: &str) -> Self {
+        Diagnostic {
+            variant: variant.to_owned(),
+            location: DiagnosticLocation::PathSegment(index),
+            value: Some(value.to_owned()),
+            error: None,
+        }
+    }
+
+    /// A query string value failed to parse. A `None` value means the key was missing or did not have exactly one value.
+    pub fn query_value(variant: &str, key: &str, value: Option<&str>) -> Self {
+        Diagnostic {
+            variant: variant.to_owned(),
+            location: DiagnosticLocation::QueryKey(key.to_owned()),
+            value: value.map(str::to_owned),
+            error: match value {
+                None => Some("expected exactly one value".to_owned()),
+                Some(_) => None,
+            },
+        }
+    }
+
+    /// Set the description of the failure.
+    pub fn with_error(mut self, error: impl fmt::Display) -> Self {
+        self.error = Some(error.to_string());
+        self
+    }
+
+    /// Adjust a diagnostic from a nested route to refer to the outer route.
+    ///
+    /// `index` is the position of the nested route's first segment within the outer path.
+    pub fn nested(mut self, variant: &str, index: usize) -> Self {
+        self.variant = format!("{}::{}", variant, self.variant);
+        if let DiagnosticLocation::PathSegment(idx) = &mut self.location {
+            *idx += index;
+        }
+        self
+    }
+
+    /// How far into the request this failure occurred. Query string failures come after all path segments.
+    fn progress(&self) -> usize {
+        match self.location {
+            DiagnosticLocation::PathSegment(idx) => idx,
+            DiagnosticLocation::QueryKey(_) => usize::MAX,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.variant)?;
+        match (&self.location, &self.value) {
+            (DiagnosticLocation::PathSegment(idx), Some(value)) => {
+                write!(f, "invalid path segment {} {:?}", idx, value)?
+            }
+            (DiagnosticLocation::PathSegment(idx), None) => {
+                write!(f, "invalid path segment {}", idx)?
+            }
+            (DiagnosticLocation::QueryKey(key), Some(value)) => write!(
+                f,
+                "invalid value {:?} for query string parameter {:?}",
+                value, key
+            )?,
+            (DiagnosticLocation::QueryKey(key), None) => {
+                write!(f, "invalid query string parameter {:?}", key)?
+            }
+        }
+        match &self.error {
+            Some(error) => write!(f, ": {}", error),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Tracks the closest failed match while parsing. Used by the `Route` derive macro.
+#[derive(Debug, Default)]
+pub struct ClosestMatch(Option<Box<Diagnostic>>);
+
+impl ClosestMatch {
+    /// Record a failure, keeping the earliest variant which got furthest into the request.
+    pub fn record(&mut self, diagnostic: Box<Diagnostic>) {
+        match &self.0 {
+            Some(best) if best.progress() >= diagnostic.progress() => (),
+            _ => self.0 = Some(diagnostic),
+        }
+    }
+
+    /// The final error when no variant matched.
+    pub fn into_error(self) -> RouteError {
+        match self.0 {
+            None => RouteError::NoMatch,
+            Some(diagnostic) => RouteError::Invalid(diagnostic),
+        }
+    }
+}
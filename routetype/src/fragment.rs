@@ -0,0 +1,91 @@
+use super::{raw, RenderUrl, Route, UrlBase};
+use std::fmt;
+
+/// A route together with a fragment, e.g. `/docs/intro#install`.
+///
+/// Fragments are never sent to the server, so this is only used for rendering. This deliberately does not dereference to the route, since the route's own rendering methods would leave out the fragment. Use [RouteWithFragment::route] to get at the route itself.
+///
+/// ```
+/// # use routetype::Route;
+/// #[derive(Route, Clone, PartialEq, Debug)]
+/// enum MyRoute {
+///     #[route("/docs/{page}")]
+///     Docs { page: String },
+/// }
+///
+/// let intro = MyRoute::Docs {
+///     page: "intro".to_owned(),
+/// };
+/// assert_eq!(
+///     intro.clone().with_fragment("first steps").render(),
+///     "/docs/intro#first%20steps"
+/// );
+/// assert_eq!(
+///     intro.with_fragment("faq").to_string(),
+///     "/docs/intro#faq"
+/// );
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RouteWithFragment<R> {
+    route: R,
+    fragment: String,
+}
+
+impl<R> RouteWithFragment<R> {
+    /// Attach a fragment to a route, without the leading `#`. See also [Route::with_fragment].
+    pub fn new(route: R, fragment: impl Into<String>) -> Self {
+        RouteWithFragment {
+            route,
+            fragment: fragment.into(),
+        }
+    }
+
+    /// The fragment, without the leading `#` or percent encoding.
+    pub fn fragment(&self) -> &str {
+        &self.fragment
+    }
+
+    /// The route, without the fragment.
+    pub fn route(&self) -> &R {
+        &self.route
+    }
+
+    /// Discard the fragment, keeping only the route.
+    pub fn into_route(self) -> R {
+        self.route
+    }
+}
+
+impl<R: RenderUrl> RouteWithFragment<R> {
+    /// Render the route followed by the fragment, see [raw::render_fragment].
+    pub fn render(&self) -> String {
+        let mut res = String::new();
+        self.render_to(&mut res)
+            .expect("Writing to a String cannot fail");
+        res
+    }
+
+    /// Like [RouteWithFragment::render], but writes into any [fmt::Write], see [Route::render_to].
+    pub fn render_to<W: fmt::Write + ?Sized>(&self, w: &mut W) -> fmt::Result {
+        self.route.render_url_to(w)?;
+        w.write_str(&raw::render_fragment(&self.fragment))
+    }
+
+    /// Render the route followed by the fragment for a link from a page served on `current_host`, see [Route::render_from_host].
+    pub fn render_from_host(&self, current_host: Option<&str>) -> String {
+        self.route.render_url_from_host(current_host) + &raw::render_fragment(&self.fragment)
+    }
+}
+
+impl<R: Route> RouteWithFragment<R> {
+    /// Render an absolute URL followed by the fragment, see [Route::render_absolute].
+    pub fn render_absolute(&self, base: &UrlBase) -> String {
+        self.route.render_absolute(base) + &raw::render_fragment(&self.fragment)
+    }
+}
+
+impl<R: RenderUrl> fmt::Display for RouteWithFragment<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.render_to(f)
+    }
+}
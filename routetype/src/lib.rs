@@ -5,8 +5,36 @@ pub mod raw;
 /// Route normalize, to ensure consistent and canonical representations.
 pub mod normalize;
 
-pub use routetype_derive::Route;
-use std::{borrow::Cow, collections::HashMap};
+/// Detailed information on why a route failed to parse.
+pub mod diagnostic;
+
+/// [RoutePiece] implementations for standard library types.
+mod piece;
+pub use piece::{FromStrRoutePiece, PathComponent, TryRoutePiece};
+
+/// Serving routes under a path prefix chosen at runtime.
+mod mount;
+pub use mount::{Mount, Mounted};
+
+/// Rendering absolute URLs.
+mod url_base;
+pub use url_base::{InvalidUrlBase, UrlBase};
+
+/// Attaching a `#fragment` when rendering.
+mod fragment;
+pub use fragment::RouteWithFragment;
+
+pub use routetype_derive::{Route, RoutePiece, RouteQuery};
+
+/// Used by the code generated by the derive macros, not part of the public API.
+#[doc(hidden)]
+pub mod __private {
+    /// The path segments of an incoming request, see [Normalization::normalize_parse_inline](crate::normalize::Normalization::normalize_parse_inline).
+    ///
+    /// Paths with up to four segments are stored inline, without a heap allocation.
+    pub type PathSegments<'a> = smallvec::SmallVec<[crate::PathSegment<'a>; 4]>;
+}
+use std::{borrow::Cow, collections::HashMap, fmt};
 
 use raw::*;
 
@@ -25,7 +53,10 @@ pub type PathSegment<'a> = Cow<'a, str>;
 pub type QueryPair<'a> = (Cow<'a, str>, Option<Cow<'a, str>>);
 
 /// Why parsing the route failed
+///
+/// This enum is `#[non_exhaustive]`, since [RouteError::MethodNotAllowed] was added after the initial set of variants and more may follow. Matches on it need a wildcard arm, which can usually be treated like [RouteError::NoMatch].
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum RouteError {
     /// The route failed the normalization rules specified.
     ///
@@ -34,6 +65,68 @@ pub enum RouteError {
 
     /// The route was normalized but did not match
     NoMatch,
+
+    /// The route was normalized and the path matched a variant, but a field could not be parsed.
+    ///
+    /// This is only returned by [Route::parse_diagnostic], [Route::parse] returns [RouteError::NoMatch] instead.
+    Invalid(Box<diagnostic::Diagnostic>),
+
+    /// The route matched a variant, but not for the requested HTTP method.
+    ///
+    /// This is only returned by method aware parsing such as [Route::parse_method], and lists the methods which would have matched.
+    MethodNotAllowed { allowed: Vec<&'static str> },
+}
+
+impl RouteError {
+    /// Prepend the given path segments to a normalization redirect.
+    ///
+    /// This is used when a route is nested within another route, so that the redirect points to the full path.
+    pub fn with_prefix<'a>(self, prefix: impl Iterator<Item = &'a str>) -> Self {
+        match self {
+            RouteError::NormalizationFailed(dest) => {
+                let prefix = render_path_and_query(prefix, None::<std::iter::Empty<_>>);
+                RouteError::NormalizationFailed(if prefix == "/" {
+                    dest
+                } else {
+                    // Avoid introducing a trailing slash when the nested route is its own root
+                    let dest = dest
+                        .strip_prefix('/')
+                        .filter(|rest| rest.is_empty() || rest.starts_with('?'))
+                        .unwrap_or(&dest);
+                    prefix + dest
+                })
+            }
+            e => e,
+        }
+    }
+
+    /// Produce a [RouteError::Invalid] if diagnostics are enabled, otherwise [RouteError::NoMatch]. Used by the `Route` derive macro.
+    pub fn invalid(diagnose: bool, diagnostic: impl FnOnce() -> diagnostic::Diagnostic) -> Self {
+        if diagnose {
+            RouteError::Invalid(Box::new(diagnostic()))
+        } else {
+            RouteError::NoMatch
+        }
+    }
+
+    /// Produce a [RouteError::MethodNotAllowed], dropping duplicate methods but keeping their order. Used by the `Route` derive macro.
+    pub fn method_not_allowed(methods: impl IntoIterator<Item = &'static str>) -> Self {
+        let mut allowed = Vec::new();
+        for method in methods {
+            if !allowed.contains(&method) {
+                allowed.push(method);
+            }
+        }
+        RouteError::MethodNotAllowed { allowed }
+    }
+
+    /// Convert a [RouteError::Invalid] into a plain [RouteError::NoMatch], dropping the diagnostic.
+    pub fn without_diagnostic(self) -> Self {
+        match self {
+            RouteError::Invalid(_) => RouteError::NoMatch,
+            e => e,
+        }
+    }
 }
 
 /// A type which can be parsed from and rendered to an HTTP path and query string.
@@ -44,13 +137,68 @@ pub trait Route: Sized + Clone + Send + Sync + 'static {
         query: Option<impl Iterator<Item = QueryPair<'b>>>,
     ) -> Result<Self, RouteError>;
 
+    /// Like [Self::parse], but return [RouteError::Invalid] describing the closest match instead of [RouteError::NoMatch] where possible.
+    ///
+    /// This is more expensive than [Self::parse] on failure. The default implementation just calls [Self::parse].
+    fn parse_diagnostic<'a, 'b>(
+        path: impl Iterator<Item = PathSegment<'a>>,
+        query: Option<impl Iterator<Item = QueryPair<'b>>>,
+    ) -> Result<Self, RouteError> {
+        Self::parse(path, query)
+    }
+
+    /// Like [Self::parse], but also match on the HTTP method and host of the request, see [RequestInfo].
+    ///
+    /// If a variant only failed to match because of the method, returns [RouteError::MethodNotAllowed]. The default implementation ignores the request.
+    fn parse_request<'a, 'b>(
+        _request: RequestInfo<'_>,
+        path: impl Iterator<Item = PathSegment<'a>>,
+        query: Option<impl Iterator<Item = QueryPair<'b>>>,
+    ) -> Result<Self, RouteError> {
+        Self::parse(path, query)
+    }
+
+    /// Combination of [Self::parse_request] and [Self::parse_diagnostic].
+    fn parse_request_diagnostic<'a, 'b>(
+        _request: RequestInfo<'_>,
+        path: impl Iterator<Item = PathSegment<'a>>,
+        query: Option<impl Iterator<Item = QueryPair<'b>>>,
+    ) -> Result<Self, RouteError> {
+        Self::parse_diagnostic(path, query)
+    }
+
+    /// Like [Self::parse], but only match variants which accept the given HTTP method, e.g. `GET`.
+    ///
+    /// This is [Self::parse_request] without a host.
+    fn parse_method<'a, 'b>(
+        method: &str,
+        path: impl Iterator<Item = PathSegment<'a>>,
+        query: Option<impl Iterator<Item = QueryPair<'b>>>,
+    ) -> Result<Self, RouteError> {
+        Self::parse_request(RequestInfo::method(method), path, query)
+    }
+
+    /// Combination of [Self::parse_method] and [Self::parse_diagnostic].
+    fn parse_method_diagnostic<'a, 'b>(
+        method: &str,
+        path: impl Iterator<Item = PathSegment<'a>>,
+        query: Option<impl Iterator<Item = QueryPair<'b>>>,
+    ) -> Result<Self, RouteError> {
+        Self::parse_request_diagnostic(RequestInfo::method(method), path, query)
+    }
+
     /// Produce a `Vec` with the path segments.
     ///
     /// Note that the output from this is assumed to be normalized.
-    fn path(&self) -> Vec<PathSegment>;
+    fn path(&self) -> Vec<PathSegment<'_>>;
 
     /// Produce a `Vec` with the query string pairs.
-    fn query(&self) -> Option<Vec<QueryPair>>;
+    fn query(&self) -> Option<Vec<QueryPair<'_>>>;
+
+    /// The host this route is served from, if it is restricted to one, e.g. with `#[route(host = "...")]`.
+    fn host(&self) -> Option<String> {
+        None
+    }
 
     /// Helper function that parses from a string instead of iterators.
     ///
@@ -60,6 +208,42 @@ pub trait Route: Sized + Clone + Send + Sync + 'static {
         Self::parse(path, query)
     }
 
+    /// Like [Self::parse_str], but using [Self::parse_diagnostic].
+    fn parse_str_diagnostic(path_and_query: &str) -> Result<Self, RouteError> {
+        let (path, query) = parse_path_and_query(path_and_query);
+        Self::parse_diagnostic(path, query)
+    }
+
+    /// Like [Self::parse_str], but using [Self::parse_method].
+    fn parse_str_method(method: &str, path_and_query: &str) -> Result<Self, RouteError> {
+        let (path, query) = parse_path_and_query(path_and_query);
+        Self::parse_method(method, path, query)
+    }
+
+    /// Like [Self::parse_str], but using [Self::parse_method_diagnostic].
+    fn parse_str_method_diagnostic(method: &str, path_and_query: &str) -> Result<Self, RouteError> {
+        let (path, query) = parse_path_and_query(path_and_query);
+        Self::parse_method_diagnostic(method, path, query)
+    }
+
+    /// Like [Self::parse_str], but using [Self::parse_request].
+    fn parse_str_request(
+        request: RequestInfo<'_>,
+        path_and_query: &str,
+    ) -> Result<Self, RouteError> {
+        let (path, query) = parse_path_and_query(path_and_query);
+        Self::parse_request(request, path, query)
+    }
+
+    /// Like [Self::parse_str], but using [Self::parse_request_diagnostic].
+    fn parse_str_request_diagnostic(
+        request: RequestInfo<'_>,
+        path_and_query: &str,
+    ) -> Result<Self, RouteError> {
+        let (path, query) = parse_path_and_query(path_and_query);
+        Self::parse_request_diagnostic(request, path, query)
+    }
+
     /// Like [Self::parse_str], but takes the path and query string as separate strings.
     ///
     /// This method will automatically strip a leading question mark from the query string, if present.
@@ -78,7 +262,18 @@ pub trait Route: Sized + Clone + Send + Sync + 'static {
     ///
     /// For details on the exact output format, see [render_path_and_query].
     fn render(&self) -> String {
-        render_path_and_query(
+        let mut res = String::new();
+        self.render_to(&mut res)
+            .expect("Writing to a String cannot fail");
+        res
+    }
+
+    /// Like [Self::render], but writes into any [fmt::Write], such as a [fmt::Formatter].
+    ///
+    /// The default implementation goes through [Self::path] and [Self::query]. Derived implementations stream each segment and query pair through [Self::render_path_to] and [Self::render_query_to] instead, and also implement [fmt::Display] with this.
+    fn render_to<W: fmt::Write + ?Sized>(&self, w: &mut W) -> fmt::Result {
+        write_path_and_query(
+            w,
             self.path().iter().map(|x| x.as_ref()),
             self.query().as_ref().map(|query| {
                 query
@@ -87,51 +282,240 @@ pub trait Route: Sized + Clone + Send + Sync + 'static {
             }),
         )
     }
+
+    /// Write the segments of [Self::path] into a [UrlWriter], without a query string.
+    ///
+    /// Used when rendering a route nested within another route.
+    fn render_path_to<W: fmt::Write + ?Sized>(&self, writer: &mut UrlWriter<'_, W>) -> fmt::Result {
+        for seg in self.path() {
+            writer.segment(&seg)?;
+        }
+        Ok(())
+    }
+
+    /// Write the pairs of [Self::query] into a [UrlWriter].
+    ///
+    /// Used when rendering a route nested within another route.
+    fn render_query_to<W: fmt::Write + ?Sized>(
+        &self,
+        writer: &mut UrlWriter<'_, W>,
+    ) -> fmt::Result {
+        if let Some(query) = self.query() {
+            for (key, value) in &query {
+                writer.pair(key, value.as_deref())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Attach a fragment for rendering, e.g. `/docs/intro#install`. The fragment is given without the leading `#`.
+    fn with_fragment(self, fragment: impl Into<String>) -> RouteWithFragment<Self> {
+        RouteWithFragment::new(self, fragment)
+    }
+
+    /// Like [Self::render], but produces an absolute URL under `base`, e.g. for emails or `Location` headers.
+    ///
+    /// If [Self::host] gives a host, it replaces the host of `base`.
+    fn render_absolute(&self, base: &UrlBase) -> String {
+        base.render_path_and_query(
+            self.host().as_deref(),
+            &self.path(),
+            self.query().as_deref(),
+        )
+    }
+
+    /// Like [Self::render], but for a link from a page served on `current_host`.
+    ///
+    /// When [Self::host] gives a different host, this renders a scheme relative URL such as `//acme.example.com:8080/dashboard`, keeping the port of `current_host`.
+    /// Use [Self::render_absolute] to choose the scheme and port instead.
+    fn render_from_host(&self, current_host: Option<&str>) -> String {
+        match self
+            .host()
+            .and_then(|host| host_prefix(&host, current_host))
+        {
+            Some(prefix) => prefix + &self.render(),
+            None => self.render(),
+        }
+    }
 }
 
-/// A trait for values which can be a part of the path segments or query string values.
-pub trait RoutePiece: Sized {
-    /// Attempt to parse a piece from a given string.
-    fn parse_route_piece(s: &str) -> Option<Self>;
+/// A value which renders to a URL, such as a [Route], a [Mounted] route or a [RouteWithFragment].
+///
+/// This is accepted by the redirect helpers of the server integrations.
+pub trait RenderUrl {
+    /// Render the URL, e.g. with [Route::render].
+    fn render_url(&self) -> String;
+
+    /// Like [Self::render_url], but writes into any [fmt::Write], e.g. with [Route::render_to].
+    fn render_url_to<W: fmt::Write + ?Sized>(&self, w: &mut W) -> fmt::Result {
+        w.write_str(&self.render_url())
+    }
 
-    /// Render this piece into a string.
-    fn render_route_piece(&self) -> Cow<str>;
+    /// Like [Self::render_url], but for a link from a page served on `current_host`, e.g. with [Route::render_from_host].
+    fn render_url_from_host(&self, current_host: Option<&str>) -> String {
+        let _ = current_host;
+        self.render_url()
+    }
 }
 
-impl RoutePiece for String {
-    fn parse_route_piece(s: &str) -> Option<Self> {
-        Some(s.to_owned())
+impl<R: Route> RenderUrl for R {
+    fn render_url(&self) -> String {
+        self.render()
     }
 
-    fn render_route_piece(&self) -> Cow<str> {
-        Cow::Borrowed(self)
+    fn render_url_to<W: fmt::Write + ?Sized>(&self, w: &mut W) -> fmt::Result {
+        self.render_to(w)
+    }
+
+    fn render_url_from_host(&self, current_host: Option<&str>) -> String {
+        self.render_from_host(current_host)
     }
 }
 
-impl RoutePiece for i32 {
-    fn parse_route_piece(s: &str) -> Option<Self> {
-        s.parse().ok()
+impl<R: Route> RenderUrl for Mounted<R> {
+    fn render_url(&self) -> String {
+        self.render()
     }
 
-    fn render_route_piece(&self) -> Cow<str> {
-        self.to_string().into()
+    fn render_url_to<W: fmt::Write + ?Sized>(&self, w: &mut W) -> fmt::Result {
+        self.render_to(w)
+    }
+
+    fn render_url_from_host(&self, current_host: Option<&str>) -> String {
+        self.render_from_host(current_host)
     }
 }
 
-impl RoutePiece for bool {
-    fn parse_route_piece(s: &str) -> Option<Self> {
-        match s {
-            "true" => Some(true),
-            "false" => Some(false),
-            _ => None,
+impl<R: RenderUrl> RenderUrl for RouteWithFragment<R> {
+    fn render_url(&self) -> String {
+        self.render()
+    }
+
+    fn render_url_to<W: fmt::Write + ?Sized>(&self, w: &mut W) -> fmt::Result {
+        self.render_to(w)
+    }
+
+    fn render_url_from_host(&self, current_host: Option<&str>) -> String {
+        self.render_from_host(current_host)
+    }
+}
+
+/// Information about an HTTP request beyond its path and query string, see [Route::parse_request].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RequestInfo<'a> {
+    /// The HTTP method, e.g. `GET`. When absent, routes restricted to some methods match any method.
+    pub method: Option<&'a str>,
+    /// The host from the URI or `Host` header, possibly with a port. When absent, routes restricted to a host only match if the host has no fields.
+    pub host: Option<&'a str>,
+}
+
+impl<'a> RequestInfo<'a> {
+    /// A request with only a method, e.g. `GET`.
+    pub fn method(method: &'a str) -> Self {
+        RequestInfo {
+            method: Some(method),
+            host: None,
         }
     }
 
-    fn render_route_piece(&self) -> Cow<str> {
-        Cow::Borrowed(match self {
-            true => "true",
-            false => "false",
-        })
+    /// Set the host, e.g. `acme.example.com:8080`.
+    pub fn with_host(self, host: &'a str) -> Self {
+        RequestInfo {
+            host: Some(host),
+            ..self
+        }
+    }
+}
+
+/// A reusable set of query string parameters, which can be embedded in derived routes with `#[route(flatten)]`.
+///
+/// This is usually derived with `#[derive(RouteQuery)]`.
+pub trait RouteQuery: Sized {
+    /// Attempt to parse from the query string.
+    ///
+    /// `variant` is the name of the route being parsed, and `diagnose` whether to return [RouteError::Invalid], see [Route::parse_diagnostic].
+    fn parse_route_query(
+        query: &QueryMap<'_>,
+        variant: &str,
+        diagnose: bool,
+    ) -> Result<Self, RouteError>;
+
+    /// Append the query string pairs for this value.
+    fn render_route_query<'a>(&'a self, res: &mut Vec<QueryPair<'a>>);
+
+    /// Is the given query string key used by this type?
+    fn is_known_key(key: &str) -> bool;
+
+    /// Helper function that parses from a query string, without a leading question mark.
+    fn parse_query_str(query: &str) -> Result<Self, RouteError> {
+        let query = QueryMap::from_query_iter(Some(parse_query(query)));
+        Self::parse_route_query(&query, std::any::type_name::<Self>(), false)
+    }
+
+    /// Helper function that renders this value into a query string, without a leading question mark.
+    fn render_query_str(&self) -> String {
+        let mut pairs = Vec::new();
+        self.render_route_query(&mut pairs);
+        let rendered = render_path_and_query(
+            std::iter::empty(),
+            Some(
+                pairs
+                    .iter()
+                    .map(|(k, v)| (k.as_ref(), v.as_ref().map(|v| v.as_ref()))),
+            ),
+        );
+        // Strip the empty path and question mark
+        rendered["/?".len()..].to_owned()
+    }
+}
+
+/// A trait for values which can be a part of the path segments or query string values.
+///
+/// [TryRoutePiece] and [FromStrRoutePiece] provide implementations with a typed parse error, rendered with [fmt::Display].
+pub trait RoutePiece: Sized {
+    /// Attempt to parse a piece from a given string.
+    fn parse_route_piece(s: &str) -> Option<Self>;
+
+    /// Render this piece into a string.
+    fn render_route_piece(&self) -> Cow<'_, str>;
+
+    /// Like [Self::parse_route_piece], but with a description of why parsing failed, if there is one.
+    ///
+    /// This is reported in [diagnostic::Diagnostic::error] by [Route::parse_diagnostic]. By default there is no description.
+    fn parse_route_piece_diagnostic(s: &str) -> Result<Self, Option<String>> {
+        Self::parse_route_piece(s).ok_or(None)
+    }
+}
+
+/// A general purpose [TryRoutePiece::Error], describing what was expected instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidRoutePiece(pub &'static str);
+
+impl fmt::Display for InvalidRoutePiece {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidRoutePiece {}
+
+/// A trait for values which capture all of the remaining path segments.
+///
+/// This is used by rest captures in derived routes, e.g. `#[route("/static/{path*}")]`.
+pub trait RouteMultiPiece: Sized {
+    /// Attempt to parse from the remaining path segments, which may be empty.
+    fn parse_route_multi_piece(segments: &[PathSegment]) -> Option<Self>;
+
+    /// Render into individual path segments.
+    ///
+    /// Each segment is percent-encoded separately, so a segment containing a forward slash does not introduce a new segment.
+    fn render_route_multi_piece(&self) -> Vec<PathSegment<'_>>;
+
+    /// Like [Self::parse_route_multi_piece], but with a description of why parsing failed, see [RoutePiece::parse_route_piece_diagnostic].
+    fn parse_route_multi_piece_diagnostic(
+        segments: &[PathSegment],
+    ) -> Result<Self, Option<String>> {
+        Self::parse_route_multi_piece(segments).ok_or(None)
     }
 }
 
@@ -167,9 +551,25 @@ impl<'a> QueryMap<'a> {
         }
     }
 
+    /// Get all values for the given key, or an empty slice if it is absent.
+    ///
+    /// Returns `None` if the key appears at least once without a value.
+    pub fn get_all(&self, name: &str) -> Option<&[Cow<'a, str>]> {
+        match self.map.get(name) {
+            None => Some(&[]),
+            Some((0, v)) => Some(v),
+            Some(_) => None,
+        }
+    }
+
     pub fn contains(&self, name: &str) -> bool {
         self.map.contains_key(name)
     }
+
+    /// All keys present in the query string, in no particular order.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.map.keys().map(|key| &**key)
+    }
 }
 
 /// A convenience type for unstructured route handling.
@@ -196,7 +596,7 @@ impl Route for PlainRoute {
         })
     }
 
-    fn path(&self) -> Vec<PathSegment> {
+    fn path(&self) -> Vec<PathSegment<'_>> {
         normalize::Normalization::default().normalize_render_path(
             self.path
                 .iter()
@@ -205,7 +605,7 @@ impl Route for PlainRoute {
         )
     }
 
-    fn query(&self) -> Option<Vec<QueryPair>> {
+    fn query(&self) -> Option<Vec<QueryPair<'_>>> {
         self.query.as_ref().map(|query| {
             query
                 .iter()
@@ -0,0 +1,278 @@
+use super::{either::Either, raw, PathSegment, QueryPair, RequestInfo, Route, RouteError, UrlBase};
+use std::{fmt, sync::Arc};
+
+/// A path prefix chosen at runtime, such as the sub-path of a reverse proxy.
+///
+/// For a prefix known at compile time, use `#[route_prefix("...")]` on the derived type instead.
+///
+/// ```
+/// # use routetype::{Mount, Route};
+/// #[derive(Route, Clone, PartialEq, Debug)]
+/// enum MyRoute {
+///     #[route("/")]
+///     Home,
+///     #[route("/hello/{name}")]
+///     Hello { name: String },
+/// }
+///
+/// let mount = Mount::new("/app");
+/// let route = mount.parse_str::<MyRoute>("/app/hello/alice").unwrap();
+/// assert_eq!(
+///     *route.route(),
+///     MyRoute::Hello {
+///         name: "alice".to_owned()
+///     }
+/// );
+/// assert_eq!(route.render(), "/app/hello/alice");
+/// assert_eq!(route.to_string(), "/app/hello/alice");
+/// assert_eq!(mount.mount(MyRoute::Home).render(), "/app");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mount {
+    prefix: Arc<[String]>,
+}
+
+impl Mount {
+    /// Create a mount point from a path such as `/app`. Empty segments, e.g. from a trailing slash, are ignored.
+    pub fn new(prefix: &str) -> Self {
+        Mount {
+            prefix: raw::parse_path(prefix)
+                .filter(|seg| !seg.is_empty())
+                .map(|seg| seg.into_owned())
+                .collect(),
+        }
+    }
+
+    /// Wrap up a route to be rendered under this mount point.
+    pub fn mount<R: Route>(&self, route: R) -> Mounted<R> {
+        Mounted {
+            mount: self.clone(),
+            route,
+        }
+    }
+
+    /// Parse a route under this mount point, see [Route::parse].
+    ///
+    /// Paths outside of the mount point give [RouteError::NoMatch], and normalization redirects include the prefix.
+    pub fn parse<'a, 'b, R: Route>(
+        &self,
+        path: impl Iterator<Item = PathSegment<'a>>,
+        query: Option<impl Iterator<Item = QueryPair<'b>>>,
+    ) -> Result<Mounted<R>, RouteError> {
+        self.parse_with(path, query, |path, query| R::parse(path, query))
+    }
+
+    /// Like [Mount::parse], but using [Route::parse_diagnostic]. Path segment indices are relative to the mount point.
+    pub fn parse_diagnostic<'a, 'b, R: Route>(
+        &self,
+        path: impl Iterator<Item = PathSegment<'a>>,
+        query: Option<impl Iterator<Item = QueryPair<'b>>>,
+    ) -> Result<Mounted<R>, RouteError> {
+        self.parse_with(path, query, |path, query| R::parse_diagnostic(path, query))
+    }
+
+    /// Like [Mount::parse], but using [Route::parse_request], checking the method and host of the request.
+    pub fn parse_request<'a, 'b, R: Route>(
+        &self,
+        request: RequestInfo<'_>,
+        path: impl Iterator<Item = PathSegment<'a>>,
+        query: Option<impl Iterator<Item = QueryPair<'b>>>,
+    ) -> Result<Mounted<R>, RouteError> {
+        self.parse_with(path, query, |path, query| {
+            R::parse_request(request, path, query)
+        })
+    }
+
+    /// Like [Mount::parse_request], but using [Route::parse_request_diagnostic].
+    pub fn parse_request_diagnostic<'a, 'b, R: Route>(
+        &self,
+        request: RequestInfo<'_>,
+        path: impl Iterator<Item = PathSegment<'a>>,
+        query: Option<impl Iterator<Item = QueryPair<'b>>>,
+    ) -> Result<Mounted<R>, RouteError> {
+        self.parse_with(path, query, |path, query| {
+            R::parse_request_diagnostic(request, path, query)
+        })
+    }
+
+    /// Like [Mount::parse], but parses from a string, see [Route::parse_str].
+    pub fn parse_str<R: Route>(&self, path_and_query: &str) -> Result<Mounted<R>, RouteError> {
+        let (path, query) = raw::parse_path_and_query(path_and_query);
+        self.parse(path, query)
+    }
+
+    /// Like [Mount::parse_str], but using [Route::parse_diagnostic].
+    pub fn parse_str_diagnostic<R: Route>(
+        &self,
+        path_and_query: &str,
+    ) -> Result<Mounted<R>, RouteError> {
+        let (path, query) = raw::parse_path_and_query(path_and_query);
+        self.parse_diagnostic(path, query)
+    }
+
+    /// Like [Mount::parse_str], but using [Route::parse_request].
+    pub fn parse_str_request<R: Route>(
+        &self,
+        request: RequestInfo<'_>,
+        path_and_query: &str,
+    ) -> Result<Mounted<R>, RouteError> {
+        let (path, query) = raw::parse_path_and_query(path_and_query);
+        self.parse_request(request, path, query)
+    }
+
+    /// Like [Mount::parse_str], but using [Route::parse_request_diagnostic].
+    pub fn parse_str_request_diagnostic<R: Route>(
+        &self,
+        request: RequestInfo<'_>,
+        path_and_query: &str,
+    ) -> Result<Mounted<R>, RouteError> {
+        let (path, query) = raw::parse_path_and_query(path_and_query);
+        self.parse_request_diagnostic(request, path, query)
+    }
+
+    /// Strip the prefix from `path` and hand the rest off to `parse`.
+    ///
+    /// Empty segments within the prefix, e.g. from `/proxy//app`, are skipped, and give a redirect keeping the query string if the route matches.
+    #[allow(clippy::type_complexity)]
+    fn parse_with<'a, 'b, R, Path, Query>(
+        &self,
+        mut path: Path,
+        query: Option<Query>,
+        parse: impl FnOnce(
+            Path,
+            Option<Either<Query, std::vec::IntoIter<QueryPair<'b>>>>,
+        ) -> Result<R, RouteError>,
+    ) -> Result<Mounted<R>, RouteError>
+    where
+        R: Route,
+        Path: Iterator<Item = PathSegment<'a>>,
+        Query: Iterator<Item = QueryPair<'b>>,
+    {
+        let mut redirect = false;
+        for expected in self.prefix.iter() {
+            loop {
+                match path.next() {
+                    Some(seg) if seg.is_empty() => redirect = true,
+                    Some(seg) if seg == **expected => break,
+                    _ => return Err(RouteError::NoMatch),
+                }
+            }
+        }
+        if !redirect {
+            return match parse(path, query.map(Either::Left)) {
+                Ok(route) => Ok(Mounted {
+                    mount: self.clone(),
+                    route,
+                }),
+                Err(e) => Err(e.with_prefix(self.prefix.iter().map(|seg| seg.as_str()))),
+            };
+        }
+        let query: Option<Vec<QueryPair>> = query.map(Iterator::collect);
+        match parse(
+            path,
+            query.clone().map(|query| Either::Right(query.into_iter())),
+        ) {
+            Ok(route) => {
+                let mounted = self.mount(route);
+                Err(RouteError::NormalizationFailed(raw::render_path_and_query(
+                    mounted.path().iter().map(|seg| seg.as_ref()),
+                    query
+                        .as_ref()
+                        .map(|query| query.iter().map(|(k, v)| (k.as_ref(), v.as_deref()))),
+                )))
+            }
+            Err(e) => Err(e.with_prefix(self.prefix.iter().map(|seg| seg.as_str()))),
+        }
+    }
+}
+
+/// A route together with the [Mount] it was parsed from or will be rendered under.
+///
+/// This deliberately does not dereference to the route, since the route's own rendering methods would leave out the prefix. Use [Mounted::route] to get at the route itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mounted<R> {
+    mount: Mount,
+    route: R,
+}
+
+impl<R: Route> Mounted<R> {
+    /// The mount point of this route.
+    pub fn mount_point(&self) -> &Mount {
+        &self.mount
+    }
+
+    /// The route, without the mount point.
+    pub fn route(&self) -> &R {
+        &self.route
+    }
+
+    /// Discard the mount point, keeping only the route.
+    pub fn into_route(self) -> R {
+        self.route
+    }
+
+    /// The path segments of the route, with the prefix prepended, see [Route::path].
+    pub fn path(&self) -> Vec<PathSegment<'_>> {
+        let mut res: Vec<PathSegment> = self
+            .mount
+            .prefix
+            .iter()
+            .map(|seg| seg.as_str().into())
+            .collect();
+        res.extend(self.route.path());
+        res
+    }
+
+    /// The query string of the route, see [Route::query].
+    pub fn query(&self) -> Option<Vec<QueryPair<'_>>> {
+        self.route.query()
+    }
+
+    /// Render the route with the prefix prepended, see [Route::render].
+    pub fn render(&self) -> String {
+        let mut res = String::new();
+        self.render_to(&mut res)
+            .expect("Writing to a String cannot fail");
+        res
+    }
+
+    /// Like [Mounted::render], but writes into any [fmt::Write], see [Route::render_to].
+    pub fn render_to<W: fmt::Write + ?Sized>(&self, w: &mut W) -> fmt::Result {
+        raw::write_path_and_query(
+            w,
+            self.path().iter().map(|x| x.as_ref()),
+            self.query().as_ref().map(|query| {
+                query
+                    .iter()
+                    .map(|(k, v)| (k.as_ref(), v.as_ref().map(|v| v.as_ref())))
+            }),
+        )
+    }
+
+    /// Render the route with the prefix prepended for a link from a page served on `current_host`, see [Route::render_from_host].
+    pub fn render_from_host(&self, current_host: Option<&str>) -> String {
+        match self
+            .route
+            .host()
+            .and_then(|host| raw::host_prefix(&host, current_host))
+        {
+            Some(prefix) => prefix + &self.render(),
+            None => self.render(),
+        }
+    }
+
+    /// Render an absolute URL with the prefix prepended, see [Route::render_absolute].
+    pub fn render_absolute(&self, base: &UrlBase) -> String {
+        base.render_path_and_query(
+            self.route.host().as_deref(),
+            &self.path(),
+            self.query().as_deref(),
+        )
+    }
+}
+
+impl<R: Route> fmt::Display for Mounted<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.render_to(f)
+    }
+}
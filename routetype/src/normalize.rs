@@ -1,9 +1,9 @@
-use super::{PathSegment, QueryPair};
-use std::borrow::Cow;
+use super::{__private::PathSegments, raw::UrlWriter, PathSegment, QueryPair};
+use std::{borrow::Cow, fmt};
 
 /// How to normalize paths
 ///
-/// For now, this is hard-coded to a single set of rules:
+/// By default, this uses the following rules:
 ///
 /// * Repeated slashes (e.g. `/foo//bar///baz`) are collapsed (e.g. `/foo/bar/baz`)
 /// * Trailing slashes (e.g. `/foo/bar/`) are removed (e.g. `/foo/bar`)
@@ -12,23 +12,48 @@ use std::borrow::Cow;
 /// The expected behavior is that, if normalization fails, your application will generate
 /// a redirect to the normalized path.
 ///
-/// In the future, this may be configurable.
-#[derive(Clone, Debug)]
+/// The rules can be changed with [Normalization::trailing_slash], [Normalization::duplicate_slashes], and [Normalization::case_insensitive].
+/// All of these are `const fn`s, so a `Normalization` can be defined as a constant:
+///
+/// ```rust
+/// # use routetype::normalize::*;
+/// const NORMALIZATION: Normalization = Normalization::new()
+///     .trailing_slash(TrailingSlash::Require)
+///     .duplicate_slashes(DuplicateSlashes::Keep);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Normalization {
-    rules: NormalizationRules,
+    trailing_slash: TrailingSlash,
+    duplicate_slashes: DuplicateSlashes,
+    case_insensitive: bool,
 }
 
 impl Default for Normalization {
     fn default() -> Self {
-        Normalization {
-            rules: NormalizationRules::NoTrailing,
-        }
+        Self::new()
     }
 }
 
-#[derive(Clone, Debug)]
-enum NormalizationRules {
-    NoTrailing,
+/// What to do with a trailing slash on a path, e.g. `/foo/bar/`.
+///
+/// The root path `/` is never affected by this policy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrailingSlash {
+    /// Redirect `/foo/bar/` to `/foo/bar`. This is the default.
+    Remove,
+    /// Redirect `/foo/bar` to `/foo/bar/`, and render paths with a trailing slash.
+    Require,
+    /// Accept both `/foo/bar` and `/foo/bar/` without a redirect, and render paths without a trailing slash.
+    Ignore,
+}
+
+/// What to do with repeated slashes within a path, e.g. `/foo//bar`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DuplicateSlashes {
+    /// Redirect `/foo//bar` to `/foo/bar`. This is the default.
+    Collapse,
+    /// Leave the path alone, producing an empty segment between `foo` and `bar`.
+    Keep,
 }
 
 fn drop_one(s: &mut Cow<str>) {
@@ -36,6 +61,67 @@ fn drop_one(s: &mut Cow<str>) {
 }
 
 impl Normalization {
+    /// The default normalization rules, usable in a `const` context.
+    pub const fn new() -> Self {
+        Normalization {
+            trailing_slash: TrailingSlash::Remove,
+            duplicate_slashes: DuplicateSlashes::Collapse,
+            case_insensitive: false,
+        }
+    }
+
+    /// Set the policy for trailing slashes.
+    pub const fn trailing_slash(mut self, trailing_slash: TrailingSlash) -> Self {
+        self.trailing_slash = trailing_slash;
+        self
+    }
+
+    /// Set the policy for repeated slashes.
+    pub const fn duplicate_slashes(mut self, duplicate_slashes: DuplicateSlashes) -> Self {
+        self.duplicate_slashes = duplicate_slashes;
+        self
+    }
+
+    /// Whether literal path segments should be compared ignoring ASCII case.
+    ///
+    /// This does not affect [Normalization::normalize_parse], it is up to the route parser to respect it.
+    pub const fn case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// Check whether a path segment matches a literal, respecting [Normalization::case_insensitive].
+    pub fn literal_matches(&self, segment: &str, literal: &str) -> bool {
+        if self.case_insensitive {
+            segment.eq_ignore_ascii_case(literal)
+        } else {
+            segment == literal
+        }
+    }
+
+    /// Strip a literal prefix and suffix from a path segment, respecting [Normalization::case_insensitive].
+    ///
+    /// This is used by derived routes for segments like `{name}.json`, returning `None` if the segment does not match.
+    pub fn strip_affixes<'a>(
+        &self,
+        segment: &'a str,
+        prefix: &str,
+        suffix: &str,
+    ) -> Option<&'a str> {
+        let end = segment.len().checked_sub(suffix.len())?;
+        if end < prefix.len() {
+            return None;
+        }
+        let inner = segment.get(prefix.len()..end)?;
+        if self.literal_matches(&segment[..prefix.len()], prefix)
+            && self.literal_matches(&segment[end..], suffix)
+        {
+            Some(inner)
+        } else {
+            None
+        }
+    }
+
     /// Apply normalization rules for incoming route, either returning the unmodified path and query or the normalized version.
     pub fn normalize_parse<'a, 'b>(
         &self,
@@ -48,9 +134,62 @@ impl Normalization {
         ),
         String,
     > {
-        let mut path = path.collect::<Vec<PathSegment<'a>>>();
-        if path.contains(&Cow::Borrowed("")) {
-            let path = path.iter().filter(|s| !s.is_empty()).map(|s| s.as_ref());
+        self.normalize_parse_inline(path, query)
+            .map(|(path, query)| (path.into_vec(), query))
+    }
+
+    /// Same as [Normalization::normalize_parse], but avoids a heap allocation for short paths. Used by derived routes.
+    #[doc(hidden)]
+    pub fn normalize_parse_inline<'a, 'b>(
+        &self,
+        path: impl Iterator<Item = PathSegment<'a>>,
+        query: Option<impl Iterator<Item = QueryPair<'b>>>,
+    ) -> Result<
+        (
+            PathSegments<'a>,
+            Option<impl Iterator<Item = QueryPair<'b>>>,
+        ),
+        String,
+    > {
+        let mut path = path.collect::<PathSegments<'a>>();
+        let mut redirect = false;
+
+        let trailing = matches!(path.last(), Some(s) if s.is_empty());
+        match self.trailing_slash {
+            TrailingSlash::Remove => {
+                while matches!(path.last(), Some(s) if s.is_empty()) {
+                    path.pop();
+                    redirect = true;
+                }
+            }
+            TrailingSlash::Require => {
+                if trailing {
+                    path.pop();
+                } else if !path.is_empty() {
+                    redirect = true;
+                }
+            }
+            TrailingSlash::Ignore => {
+                if trailing {
+                    path.pop();
+                }
+            }
+        }
+
+        if self.duplicate_slashes == DuplicateSlashes::Collapse && path.contains(&Cow::Borrowed(""))
+        {
+            path.retain(|s| !s.is_empty());
+            redirect = true;
+        }
+
+        if redirect {
+            let path = path.iter().map(|s| s.as_ref()).chain(
+                if self.trailing_slash == TrailingSlash::Require && !path.is_empty() {
+                    Some("")
+                } else {
+                    None
+                },
+            );
             // FIXME make this more elegant
             match query {
                 None => {
@@ -66,7 +205,7 @@ impl Normalization {
                             k.as_ref(),
                             v.as_ref().map(|v| match v {
                                 Cow::Borrowed(s) => *s,
-                                Cow::Owned(s) => &s,
+                                Cow::Owned(s) => s,
                             }),
                         )
                     });
@@ -75,8 +214,7 @@ impl Normalization {
             }
         }
         path.iter_mut().for_each(|s| {
-            if !s.contains(|c| c != '-') {
-                assert!(!s.is_empty());
+            if !s.is_empty() && !s.contains(|c| c != '-') {
                 drop_one(s);
             }
         });
@@ -84,24 +222,50 @@ impl Normalization {
     }
 
     /// Apply normalization rules for outgoing path segments
-    pub fn normalize_render_path<'a>(
-        &self,
-        mut path: Vec<PathSegment<'a>>,
-    ) -> Vec<PathSegment<'a>> {
-        path.iter_mut().for_each(|seg| {
-            if !seg.contains(|c| c != '-') {
-                // It's only dashes. Let's handle a few simple cases to cut down on heap allocations
-                match seg.len() {
-                    0 => *seg = Cow::Borrowed("-"),
-                    1 => *seg = Cow::Borrowed("--"),
-                    2 => *seg = Cow::Borrowed("---"),
-                    3 => *seg = Cow::Borrowed("----"),
-                    _ => seg.to_mut().push('-'),
-                }
-            }
-        });
+    pub fn normalize_render_path<'a>(&self, path: Vec<PathSegment<'a>>) -> Vec<PathSegment<'a>> {
+        let mut path = self.escape_render_path(path);
+        if self.trailing_slash == TrailingSlash::Require && !path.is_empty() {
+            path.push(Cow::Borrowed(""));
+        }
         path
     }
+
+    /// Like [Normalization::normalize_render_path], but never adds a trailing slash.
+    ///
+    /// This is useful when more segments will be appended afterwards, such as for nested routes.
+    pub fn escape_render_path<'a>(&self, mut path: Vec<PathSegment<'a>>) -> Vec<PathSegment<'a>> {
+        path.iter_mut()
+            .for_each(|seg| *seg = self.escape_render_segment(std::mem::take(seg)));
+        path
+    }
+
+    /// Like [Normalization::escape_render_path], but for a single segment.
+    pub fn escape_render_segment<'a>(&self, mut seg: PathSegment<'a>) -> PathSegment<'a> {
+        if !seg.contains(|c| c != '-') {
+            // It's only dashes. Let's handle a few simple cases to cut down on heap allocations
+            match seg.len() {
+                0 => seg = Cow::Borrowed("-"),
+                1 => seg = Cow::Borrowed("--"),
+                2 => seg = Cow::Borrowed("---"),
+                3 => seg = Cow::Borrowed("----"),
+                _ => seg.to_mut().push('-'),
+            }
+        }
+        seg
+    }
+
+    /// The streaming counterpart of [Normalization::normalize_render_path], called after writing the escaped segments.
+    ///
+    /// This adds the trailing slash, if required.
+    pub fn finish_render_path<W: fmt::Write + ?Sized>(
+        &self,
+        writer: &mut UrlWriter<W>,
+    ) -> fmt::Result {
+        if self.trailing_slash == TrailingSlash::Require && writer.segments() > 0 {
+            writer.segment("")?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -109,9 +273,15 @@ mod tests {
     use super::*;
 
     fn helper(path: &[&'static str]) -> Result<Vec<&'static str>, String> {
+        helper_with(Normalization::default(), path)
+    }
+
+    fn helper_with(
+        normalization: Normalization,
+        path: &[&'static str],
+    ) -> Result<Vec<&'static str>, String> {
         let q: Option<std::iter::Empty<_>> = None;
-        let (p, q) =
-            Normalization::default().normalize_parse(path.iter().copied().map(Cow::Borrowed), q)?;
+        let (p, q) = normalization.normalize_parse(path.iter().copied().map(Cow::Borrowed), q)?;
         assert!(q.is_none());
         let p = p
             .into_iter()
@@ -130,4 +300,59 @@ mod tests {
         assert_eq!(helper(&["foo", "bar"]), Ok(vec!["foo", "bar"]));
         assert_eq!(helper(&["foo", "bar", ""]), Err("/foo/bar".to_owned()));
     }
+
+    #[test]
+    fn require_trailing() {
+        let n = Normalization::new().trailing_slash(TrailingSlash::Require);
+        assert_eq!(helper_with(n, &[]), Ok(vec![]));
+        assert_eq!(helper_with(n, &["foo", "bar", ""]), Ok(vec!["foo", "bar"]));
+        assert_eq!(helper_with(n, &["foo", "bar"]), Err("/foo/bar/".to_owned()));
+        assert_eq!(
+            helper_with(n, &["foo", "", "bar"]),
+            Err("/foo/bar/".to_owned())
+        );
+        assert_eq!(
+            n.normalize_render_path(vec![Cow::Borrowed("foo"), Cow::Borrowed("")]),
+            vec!["foo", "-", ""]
+        );
+        assert_eq!(n.normalize_render_path(vec![]), Vec::<Cow<str>>::new());
+    }
+
+    #[test]
+    fn ignore_trailing() {
+        let n = Normalization::new().trailing_slash(TrailingSlash::Ignore);
+        assert_eq!(helper_with(n, &["foo", "bar", ""]), Ok(vec!["foo", "bar"]));
+        assert_eq!(helper_with(n, &["foo", "bar"]), Ok(vec!["foo", "bar"]));
+        assert_eq!(helper_with(n, &["foo", "", ""]), Err("/foo".to_owned()));
+    }
+
+    #[test]
+    fn keep_duplicates() {
+        let n = Normalization::new().duplicate_slashes(DuplicateSlashes::Keep);
+        assert_eq!(
+            helper_with(n, &["foo", "", "bar"]),
+            Ok(vec!["foo", "", "bar"])
+        );
+        assert_eq!(helper_with(n, &["foo", "", ""]), Err("/foo".to_owned()));
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let n = Normalization::new().case_insensitive(true);
+        assert!(n.literal_matches("FoO", "foo"));
+        assert!(!Normalization::new().literal_matches("FoO", "foo"));
+    }
+
+    #[test]
+    fn strip_affixes() {
+        let n = Normalization::new();
+        assert_eq!(n.strip_affixes("v12", "v", ""), Some("12"));
+        assert_eq!(n.strip_affixes("a.json", "", ".json"), Some("a"));
+        assert_eq!(n.strip_affixes(".json", "", ".json"), Some(""));
+        assert_eq!(n.strip_affixes("a.JSON", "", ".json"), None);
+        assert_eq!(n.strip_affixes("ab", "ab", "b"), None);
+        assert_eq!(n.strip_affixes("é", "x", ""), None);
+        let n = n.case_insensitive(true);
+        assert_eq!(n.strip_affixes("a.JSON", "", ".json"), Some("a"));
+    }
 }
@@ -0,0 +1,407 @@
+use super::{InvalidRoutePiece, PathSegment, RouteMultiPiece, RoutePiece};
+use std::{borrow::Cow, fmt, path::Path, str::FromStr};
+
+/// A [RoutePiece] which reports why parsing failed with a typed error, and is rendered with [fmt::Display].
+///
+/// ```
+/// # use routetype::TryRoutePiece;
+/// # use std::fmt;
+/// struct Page(u32);
+///
+/// impl TryRoutePiece for Page {
+///     type Error = &'static str;
+///
+///     fn try_parse_route_piece(s: &str) -> Result<Self, Self::Error> {
+///         match s.parse() {
+///             Ok(page) if page > 0 => Ok(Page(page)),
+///             _ => Err("page must be a positive integer"),
+///         }
+///     }
+/// }
+///
+/// impl fmt::Display for Page {
+///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+///         self.0.fmt(f)
+///     }
+/// }
+/// ```
+///
+/// The `Display` output must parse back to the same value for routes to round trip.
+pub trait TryRoutePiece: fmt::Display + Sized {
+    /// Why parsing failed.
+    ///
+    /// This is reported in [Diagnostic::error](super::diagnostic::Diagnostic::error) by [Route::parse_diagnostic](super::Route::parse_diagnostic).
+    type Error: fmt::Display;
+
+    /// Attempt to parse a piece from a given string.
+    fn try_parse_route_piece(s: &str) -> Result<Self, Self::Error>;
+}
+
+impl<T: TryRoutePiece> RoutePiece for T {
+    fn parse_route_piece(s: &str) -> Option<Self> {
+        T::try_parse_route_piece(s).ok()
+    }
+
+    fn render_route_piece(&self) -> Cow<'_, str> {
+        self.to_string().into()
+    }
+
+    fn parse_route_piece_diagnostic(s: &str) -> Result<Self, Option<String>> {
+        T::try_parse_route_piece(s).map_err(|e| Some(e.to_string()))
+    }
+}
+
+/// Opt in to a [TryRoutePiece] implementation based on [FromStr] and [fmt::Display].
+///
+/// ```
+/// # use routetype::FromStrRoutePiece;
+/// # use std::{fmt, str::FromStr};
+/// struct UserId(u64);
+///
+/// impl FromStr for UserId {
+///     type Err = std::num::ParseIntError;
+///     fn from_str(s: &str) -> Result<Self, Self::Err> {
+///         s.parse().map(UserId)
+///     }
+/// }
+///
+/// impl fmt::Display for UserId {
+///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+///         self.0.fmt(f)
+///     }
+/// }
+///
+/// impl FromStrRoutePiece for UserId {}
+/// ```
+///
+/// The `Display` output must parse back to the same value for routes to round trip.
+pub trait FromStrRoutePiece: FromStr + fmt::Display {}
+
+impl<T> TryRoutePiece for T
+where
+    T: FromStrRoutePiece,
+    T::Err: fmt::Display,
+{
+    type Error = T::Err;
+
+    fn try_parse_route_piece(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+macro_rules! from_str_route_pieces {
+    ($($t:ty),* $(,)?) => {
+        $(impl FromStrRoutePiece for $t {})*
+    };
+}
+
+from_str_route_pieces!(
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    f32,
+    f64,
+    char,
+    std::num::NonZeroI8,
+    std::num::NonZeroI16,
+    std::num::NonZeroI32,
+    std::num::NonZeroI64,
+    std::num::NonZeroI128,
+    std::num::NonZeroIsize,
+    std::num::NonZeroU8,
+    std::num::NonZeroU16,
+    std::num::NonZeroU32,
+    std::num::NonZeroU64,
+    std::num::NonZeroU128,
+    std::num::NonZeroUsize,
+    std::net::IpAddr,
+    std::net::Ipv4Addr,
+    std::net::Ipv6Addr,
+);
+
+impl RoutePiece for String {
+    fn parse_route_piece(s: &str) -> Option<Self> {
+        Some(s.to_owned())
+    }
+
+    fn render_route_piece(&self) -> Cow<'_, str> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl RoutePiece for Cow<'static, str> {
+    fn parse_route_piece(s: &str) -> Option<Self> {
+        Some(Cow::Owned(s.to_owned()))
+    }
+
+    fn render_route_piece(&self) -> Cow<'_, str> {
+        Cow::Borrowed(self)
+    }
+}
+
+/// Unlike the [FromStr] implementation, this only accepts the exact strings `true` and `false`.
+impl TryRoutePiece for bool {
+    type Error = InvalidRoutePiece;
+
+    fn try_parse_route_piece(s: &str) -> Result<Self, Self::Error> {
+        match s {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(InvalidRoutePiece("true or false")),
+        }
+    }
+}
+
+impl<T: RoutePiece> RouteMultiPiece for Vec<T> {
+    fn parse_route_multi_piece(segments: &[PathSegment]) -> Option<Self> {
+        segments
+            .iter()
+            .map(|seg| T::parse_route_piece(seg))
+            .collect()
+    }
+
+    fn parse_route_multi_piece_diagnostic(
+        segments: &[PathSegment],
+    ) -> Result<Self, Option<String>> {
+        segments
+            .iter()
+            .map(|seg| T::parse_route_piece_diagnostic(seg))
+            .collect()
+    }
+
+    fn render_route_multi_piece(&self) -> Vec<PathSegment<'_>> {
+        self.iter().map(T::render_route_piece).collect()
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl FromStrRoutePiece for uuid::Uuid {}
+
+#[cfg(feature = "semver")]
+impl FromStrRoutePiece for semver::Version {}
+
+/// Rendered as `YYYY-MM-DD`.
+#[cfg(feature = "chrono")]
+impl FromStrRoutePiece for chrono::NaiveDate {}
+
+/// Rendered in RFC 3339 format, e.g. `2021-04-01T12:30:00Z`. Any RFC 3339 offset is accepted when parsing.
+#[cfg(feature = "chrono")]
+impl RoutePiece for chrono::DateTime<chrono::Utc> {
+    fn parse_route_piece(s: &str) -> Option<Self> {
+        Self::parse_route_piece_diagnostic(s).ok()
+    }
+
+    fn parse_route_piece_diagnostic(s: &str) -> Result<Self, Option<String>> {
+        chrono::DateTime::parse_from_rfc3339(s)
+            .map(|dt| dt.with_timezone(&chrono::Utc))
+            .map_err(|e| Some(e.to_string()))
+    }
+
+    fn render_route_piece(&self) -> Cow<'_, str> {
+        self.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true)
+            .into()
+    }
+}
+
+/// Rendered as `YYYY-MM-DD`.
+#[cfg(feature = "time")]
+impl RoutePiece for time::Date {
+    fn parse_route_piece(s: &str) -> Option<Self> {
+        Self::parse_route_piece_diagnostic(s).ok()
+    }
+
+    fn parse_route_piece_diagnostic(s: &str) -> Result<Self, Option<String>> {
+        time::Date::parse(s, TIME_DATE_FORMAT).map_err(|e| Some(e.to_string()))
+    }
+
+    fn render_route_piece(&self) -> Cow<'_, str> {
+        self.format(TIME_DATE_FORMAT)
+            .expect("formatting a date cannot fail")
+            .into()
+    }
+}
+
+#[cfg(feature = "time")]
+const TIME_DATE_FORMAT: &[time::format_description::FormatItem<'_>] =
+    time::macros::format_description!("[year]-[month]-[day]");
+
+/// A single file name which is safe to join onto a directory.
+///
+/// Path segments are percent decoded, so a plain `String` segment may contain `/` or be `..`.
+/// This type rejects empty strings, `.`, `..`, and anything containing `/`, `\`, `:` or a NUL byte.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PathComponent(String);
+
+impl PathComponent {
+    /// Check that a string is a single, safe path component.
+    pub fn new(s: impl Into<String>) -> Result<Self, InvalidRoutePiece> {
+        let s = s.into();
+        if s.is_empty() || s == "." || s == ".." || s.contains(['/', '\\', ':', '\0']) {
+            Err(InvalidRoutePiece("a single path component"))
+        } else {
+            Ok(PathComponent(s))
+        }
+    }
+
+    /// The path component as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Unwrap the underlying `String`.
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+impl AsRef<str> for PathComponent {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for PathComponent {
+    fn as_ref(&self) -> &Path {
+        self.0.as_ref()
+    }
+}
+
+impl fmt::Display for PathComponent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl RoutePiece for PathComponent {
+    fn parse_route_piece(s: &str) -> Option<Self> {
+        PathComponent::new(s).ok()
+    }
+
+    fn parse_route_piece_diagnostic(s: &str) -> Result<Self, Option<String>> {
+        PathComponent::new(s).map_err(|e| Some(e.to_string()))
+    }
+
+    fn render_route_piece(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::*;
+    use std::net::IpAddr;
+    use std::num::NonZeroU64;
+
+    fn round_trip<T: RoutePiece + PartialEq>(x: T) -> bool {
+        T::parse_route_piece(&x.render_route_piece()) == Some(x)
+    }
+
+    /// Round trip through a rendered URL, as both a path segment and a query string value.
+    #[cfg(any(
+        feature = "uuid",
+        feature = "chrono",
+        feature = "time",
+        feature = "semver"
+    ))]
+    fn round_trip_route<T: RoutePiece + PartialEq>(x: T) -> bool {
+        use crate::{PlainRoute, Route};
+
+        let piece = x.render_route_piece().into_owned();
+        let plainroute = PlainRoute {
+            path: vec![piece.clone()],
+            query: Some(vec![("piece".to_owned(), Some(piece))]),
+        };
+        let parses_back = |s: &str| T::parse_route_piece(s).as_ref() == Some(&x);
+        match PlainRoute::parse_str(&plainroute.render()) {
+            Ok(parsed) => {
+                matches!(&parsed.path[..], [seg] if parses_back(seg))
+                    && matches!(parsed.query.as_deref(), Some([(_, Some(value))]) if parses_back(value))
+            }
+            Err(_) => false,
+        }
+    }
+
+    quickcheck! {
+        fn prop_round_trip_ints(x: i64, y: u128, z: usize) -> bool {
+            round_trip(x) && round_trip(y) && round_trip(z)
+        }
+
+        fn prop_round_trip_floats(x: f64) -> bool {
+            x.is_nan() || round_trip(x)
+        }
+
+        fn prop_round_trip_misc(c: char, n: NonZeroU64, ip: IpAddr) -> bool {
+            round_trip(c) && round_trip(n) && round_trip(ip)
+        }
+    }
+
+    #[cfg(feature = "uuid")]
+    quickcheck! {
+        fn prop_round_trip_uuid(x: u128) -> bool {
+            round_trip_route(uuid::Uuid::from_u128(x))
+        }
+    }
+
+    #[cfg(feature = "chrono")]
+    quickcheck! {
+        fn prop_round_trip_chrono_naive_date(days: i32) -> TestResult {
+            match chrono::NaiveDate::from_num_days_from_ce_opt(days) {
+                None => TestResult::discard(),
+                Some(date) => TestResult::from_bool(round_trip_route(date)),
+            }
+        }
+
+        fn prop_round_trip_chrono_datetime(secs: i64, nanos: u32) -> TestResult {
+            use chrono::TimeZone;
+            // Stay within four digit years, which is all RFC 3339 supports
+            let secs = secs.rem_euclid(253_402_300_800);
+            match chrono::Utc.timestamp_opt(secs, nanos % 1_000_000_000).single() {
+                None => TestResult::discard(),
+                Some(dt) => TestResult::from_bool(round_trip_route(dt)),
+            }
+        }
+    }
+
+    #[cfg(feature = "time")]
+    quickcheck! {
+        fn prop_round_trip_time_date(julian_day: i32) -> TestResult {
+            match time::Date::from_julian_day(julian_day) {
+                Err(_) => TestResult::discard(),
+                Ok(date) => TestResult::from_bool(round_trip_route(date)),
+            }
+        }
+    }
+
+    #[cfg(feature = "semver")]
+    quickcheck! {
+        fn prop_round_trip_semver(major: u64, minor: u64, patch: u64, pre: Option<u32>, build: Option<u32>) -> bool {
+            let mut version = semver::Version::new(major, minor, patch);
+            if let Some(pre) = pre {
+                version.pre = semver::Prerelease::new(&format!("rc.{}", pre)).unwrap();
+            }
+            if let Some(build) = build {
+                version.build = semver::BuildMetadata::new(&format!("build.{}", build)).unwrap();
+            }
+            round_trip_route(version)
+        }
+    }
+
+    #[test]
+    fn path_component() {
+        for bad in &["", ".", "..", "a/b", "a\\b", "C:", "a\0b"] {
+            assert_eq!(PathComponent::parse_route_piece(bad), None);
+        }
+        let good = PathComponent::parse_route_piece("..hidden").unwrap();
+        assert_eq!(Path::new("/srv").join(good), Path::new("/srv/..hidden"));
+    }
+}
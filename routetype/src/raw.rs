@@ -1,6 +1,7 @@
 use super::either::Either;
 use super::{PathSegment, QueryPair};
-use std::borrow::Cow;
+use percent_encoding::{AsciiSet, CONTROLS};
+use std::{borrow::Cow, fmt};
 
 /** Parse a string containing both a path and query string.
 
@@ -18,8 +19,8 @@ assert!(parse_path_and_query("/foo/bar?").1.is_some());
 pub fn parse_path_and_query(
     path_and_query: &str,
 ) -> (
-    impl Iterator<Item = PathSegment>,
-    Option<impl Iterator<Item = QueryPair>>,
+    impl Iterator<Item = PathSegment<'_>>,
+    Option<impl Iterator<Item = QueryPair<'_>>>,
 ) {
     match path_and_query.find('?') {
         None => (parse_path(path_and_query), None),
@@ -31,7 +32,7 @@ pub fn parse_path_and_query(
     }
 }
 
-fn decode(s: &str) -> Cow<str> {
+fn decode(s: &str) -> Cow<'_, str> {
     percent_encoding::percent_decode_str(s).decode_utf8_lossy()
 }
 
@@ -55,7 +56,7 @@ let segments: Vec<PathSegment> = parse_path("foo//bar/").collect();
 assert_eq!(segments, vec!["foo", "", "bar", ""]);
 ```
 */
-pub fn parse_path(mut path: &str) -> impl Iterator<Item = PathSegment> {
+pub fn parse_path(mut path: &str) -> impl Iterator<Item = PathSegment<'_>> {
     if path.bytes().next() == Some(b'/') {
         path = &path[1..];
     }
@@ -97,7 +98,7 @@ assert_eq!(pairs[0].1, Some(Cow::Borrowed("value")));
 ```
 
 */
-pub fn parse_query(query: &str) -> impl Iterator<Item = QueryPair> {
+pub fn parse_query(query: &str) -> impl Iterator<Item = QueryPair<'_>> {
     if query.is_empty() {
         Either::Left(std::iter::empty())
     } else {
@@ -105,7 +106,7 @@ pub fn parse_query(query: &str) -> impl Iterator<Item = QueryPair> {
     }
 }
 
-fn parse_query_pair(pair: &str) -> QueryPair {
+fn parse_query_pair(pair: &str) -> QueryPair<'_> {
     match pair.find('=') {
         None => (decode(pair), None),
         Some(idx) => {
@@ -183,59 +184,255 @@ where
     Path: Iterator<Item = &'a str>,
     Query: Iterator<Item = (&'b str, Option<&'b str>)>,
 {
-    use percent_encoding::{AsciiSet, CONTROLS};
+    let mut res = String::new();
+    write_path_and_query(&mut res, path, query).expect("Writing to a String cannot fail");
+    res
+}
 
-    fn encode_append(res: &mut String, s: &str, set: &'static AsciiSet) {
-        for s in percent_encoding::utf8_percent_encode(s, set) {
-            *res += s;
+/// Like [render_path_and_query], but writes into any [fmt::Write] instead of allocating a `String`.
+pub fn write_path_and_query<'a, 'b, W, Path, Query>(
+    out: &mut W,
+    path: Path,
+    query: Option<Query>,
+) -> fmt::Result
+where
+    W: fmt::Write + ?Sized,
+    Path: Iterator<Item = &'a str>,
+    Query: Iterator<Item = (&'b str, Option<&'b str>)>,
+{
+    let mut writer = UrlWriter::new(out);
+    for segment in path {
+        writer.segment(segment)?;
+    }
+    if let Some(query) = query {
+        writer.start_query()?;
+        for (key, value) in query {
+            writer.pair(key, value)?;
         }
     }
+    writer.finish()
+}
 
-    // https://url.spec.whatwg.org/#query-percent-encode-set
-    const BASE: AsciiSet = CONTROLS
-        .add(b'%')
-        .add(b' ')
-        .add(b'"')
-        .add(b'#')
-        .add(b'<')
-        .add(b'>');
-    const QUERY_SET: AsciiSet = BASE.add(b'=').add(b'&'); // special meaning in query
-    const PATH_SET: AsciiSet = BASE.add(b'?').add(b'`').add(b'{').add(b'}').add(b'/');
+// https://url.spec.whatwg.org/#query-percent-encode-set
+const BASE_SET: AsciiSet = CONTROLS
+    .add(b'%')
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'<')
+    .add(b'>');
+const QUERY_SET: AsciiSet = BASE_SET.add(b'=').add(b'&'); // special meaning in query
+const PATH_SET: AsciiSet = BASE_SET.add(b'?').add(b'`').add(b'{').add(b'}').add(b'/');
 
-    let mut res = String::new();
-    for segment in path {
-        res.push('/');
-        encode_append(&mut res, segment, &PATH_SET);
+/** Streams percent encoded path segments and query string pairs into a [fmt::Write], in the same format as [render_path_and_query].
+
+This is the building block of [Route::render_to](super::Route::render_to), and avoids collecting the segments and pairs first.
+
+```rust
+# use routetype::raw::UrlWriter;
+let mut res = String::new();
+let mut writer = UrlWriter::new(&mut res);
+writer.segment("hello").unwrap();
+writer.segment("wor/ld").unwrap();
+writer.pair("page", Some("2")).unwrap();
+writer.finish().unwrap();
+assert_eq!(res, "/hello/wor%2Fld?page=2");
+```
+*/
+pub struct UrlWriter<'w, W: ?Sized> {
+    out: &'w mut W,
+    segments: usize,
+    query: Option<usize>,
+}
+
+impl<'w, W: fmt::Write + ?Sized> UrlWriter<'w, W> {
+    /// Start writing a URL into `out`.
+    pub fn new(out: &'w mut W) -> Self {
+        UrlWriter {
+            out,
+            segments: 0,
+            query: None,
+        }
     }
-    if res.is_empty() {
-        res.push('/');
+
+    /// How many path segments have been written so far.
+    pub fn segments(&self) -> usize {
+        self.segments
     }
 
-    if let Some(query) = query {
-        res.push('?');
-        let mut first = true;
+    /// Write a single path segment. All segments must come before the query string.
+    pub fn segment(&mut self, segment: &str) -> fmt::Result {
+        debug_assert!(self.query.is_none(), "Path segment after the query string");
+        self.segments += 1;
+        self.out.write_char('/')?;
+        write_encoded(self.out, segment, &PATH_SET)
+    }
 
-        for (key, value) in query {
-            if first {
-                first = false;
-            } else {
-                res.push('&');
+    /// Start the query string, even if no pairs follow. This is done automatically by [UrlWriter::pair].
+    pub fn start_query(&mut self) -> fmt::Result {
+        if self.query.is_none() {
+            if self.segments == 0 {
+                self.out.write_char('/')?;
             }
+            self.out.write_char('?')?;
+            self.query = Some(0);
+        }
+        Ok(())
+    }
 
-            encode_append(&mut res, key, &QUERY_SET);
-            if let Some(value) = value {
-                res.push('=');
-                encode_append(&mut res, value, &QUERY_SET);
+    /// Write a single query string pair.
+    pub fn pair(&mut self, key: &str, value: Option<&str>) -> fmt::Result {
+        self.start_query()?;
+        if let Some(pairs) = &mut self.query {
+            if *pairs > 0 {
+                self.out.write_char('&')?;
+            }
+            *pairs += 1;
+        }
+        write_encoded(self.out, key, &QUERY_SET)?;
+        if let Some(value) = value {
+            self.out.write_char('=')?;
+            write_encoded(self.out, value, &QUERY_SET)?;
+        }
+        Ok(())
+    }
+
+    /// Finish the URL, writing a lone `/` if nothing else was written.
+    pub fn finish(self) -> fmt::Result {
+        if self.segments == 0 && self.query.is_none() {
+            self.out.write_char('/')?;
+        }
+        Ok(())
+    }
+}
+
+fn write_encoded<W: fmt::Write + ?Sized>(
+    out: &mut W,
+    s: &str,
+    set: &'static AsciiSet,
+) -> fmt::Result {
+    for s in percent_encoding::utf8_percent_encode(s, set) {
+        out.write_str(s)?;
+    }
+    Ok(())
+}
+
+/** Render a fragment, including the leading `#`.
+
+This percent-encodes the [WHATWG fragment set](https://url.spec.whatwg.org/#fragment-percent-encode-set), plus `%` itself so that the fragment decodes back to the original text.
+
+```rust
+# use routetype::raw::render_fragment;
+assert_eq!(render_fragment("install"), "#install");
+assert_eq!(render_fragment("a b#c%"), "#a%20b#c%25");
+```
+*/
+pub fn render_fragment(fragment: &str) -> String {
+    // https://url.spec.whatwg.org/#fragment-percent-encode-set
+    const FRAGMENT_SET: AsciiSet = CONTROLS
+        .add(b' ')
+        .add(b'"')
+        .add(b'<')
+        .add(b'>')
+        .add(b'`')
+        .add(b'%');
+
+    let mut res = String::from("#");
+    res.extend(percent_encoding::utf8_percent_encode(
+        fragment,
+        &FRAGMENT_SET,
+    ));
+    res
+}
+
+/** Join the elements of a query string value split on `separator`, e.g. for a `#[route(separator = ",")]` field.
+
+`%` and the characters of the separator are percent-encoded within each element, so that [split_separated] gives back the same elements.
+
+```rust
+# use routetype::raw::join_separated;
+assert_eq!(join_separated(&["a".into(), "b".into()], ","), "a,b");
+assert_eq!(join_separated(&["a,b".into(), "100%".into()], ","), "a%2Cb,100%25");
+```
+*/
+pub fn join_separated(values: &[Cow<'_, str>], separator: &str) -> String {
+    let is_special = |c: char| c == '%' || separator.contains(c);
+    let mut res = String::new();
+    for (idx, value) in values.iter().enumerate() {
+        if idx > 0 {
+            res.push_str(separator);
+        }
+        for c in value.chars() {
+            if is_special(c) {
+                let mut buf = [0; 4];
+                for b in c.encode_utf8(&mut buf).bytes() {
+                    res.push_str(&format!("%{:02X}", b));
+                }
+            } else {
+                res.push(c);
             }
         }
     }
     res
 }
 
+/** Split a query string value on `separator` and decode each element, the inverse of [join_separated].
+
+An empty value is a single empty element.
+
+```rust
+# use routetype::raw::split_separated;
+assert_eq!(split_separated("a%2Cb,100%25", ","), vec!["a,b", "100%"]);
+assert_eq!(split_separated("", ","), vec![""]);
+```
+*/
+pub fn split_separated<'a>(value: &'a str, separator: &str) -> Vec<Cow<'a, str>> {
+    value.split(separator).map(decode).collect()
+}
+
+/** Normalize the host of a request for matching, from either the URI or the `Host` header.
+
+This drops the port and a trailing dot, and lowercases the rest.
+
+```rust
+# use routetype::raw::normalize_host;
+assert_eq!(normalize_host("Acme.Example.com:8080"), "acme.example.com");
+assert_eq!(normalize_host("example.com."), "example.com");
+assert_eq!(normalize_host("[::1]:3000"), "[::1]");
+```
+*/
+pub fn normalize_host(host: &str) -> String {
+    let host = split_port(host).0;
+    host.strip_suffix('.').unwrap_or(host).to_ascii_lowercase()
+}
+
+/// The `//host:port` to put in front of a link to `host` from a page on `current_host`, or `None` if they are the same host.
+pub(crate) fn host_prefix(host: &str, current_host: Option<&str>) -> Option<String> {
+    if current_host.map(normalize_host) == Some(normalize_host(host)) {
+        return None;
+    }
+    Some(
+        match current_host.and_then(|current| split_port(current).1) {
+            Some(port) => format!("//{}:{}", host, port),
+            None => format!("//{}", host),
+        },
+    )
+}
+
+/// Split a host such as `example.com:8080` into the host and the port, if any.
+pub(crate) fn split_port(host: &str) -> (&str, Option<&str>) {
+    match host.rfind(':') {
+        // An IPv6 address contains colons, but its port comes after the closing bracket
+        Some(idx) if !host[idx..].contains(']') => (&host[..idx], Some(&host[idx + 1..])),
+        _ => (host, None),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[allow(clippy::type_complexity)]
     fn pq(s: &str) -> (Vec<String>, Option<Vec<(String, Option<String>)>>) {
         let (path, query) = parse_path_and_query(s);
         let path = path.map(|x| x.into_owned()).collect();
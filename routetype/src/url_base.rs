@@ -0,0 +1,242 @@
+use super::{raw, PathSegment, QueryPair};
+use std::{fmt, str::FromStr};
+
+/// The scheme, authority and path prefix of an application, used to render absolute URLs with [Route::render_absolute](super::Route::render_absolute).
+///
+/// ```
+/// # use routetype::{Route, UrlBase};
+/// #[derive(Route, Clone, PartialEq, Debug)]
+/// enum MyRoute {
+///     #[route("/")]
+///     Home,
+///     #[route("/hello/{name}")]
+///     Hello { name: String },
+/// }
+///
+/// let base: UrlBase = "https://app.example.com/base/".parse().unwrap();
+/// let hello = MyRoute::Hello {
+///     name: "alice".to_owned(),
+/// };
+/// assert_eq!(
+///     hello.render_absolute(&base),
+///     "https://app.example.com/base/hello/alice"
+/// );
+/// assert_eq!(
+///     MyRoute::Home.render_absolute(&base),
+///     "https://app.example.com/base"
+/// );
+///
+/// let local = UrlBase::new("http", "::1").with_port(3000);
+/// assert_eq!(MyRoute::Home.render_absolute(&local), "http://[::1]:3000/");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UrlBase {
+    scheme: String,
+    /// IPv6 addresses are kept in brackets, ready for use in a URL
+    host: String,
+    port: Option<u16>,
+    prefix: Vec<String>,
+}
+
+impl UrlBase {
+    /// Create a base URL without a port or path prefix.
+    ///
+    /// `host` is a domain name or an IP address. IPv6 addresses may be given with or without brackets.
+    pub fn new(scheme: &str, host: &str) -> Self {
+        UrlBase {
+            scheme: scheme.to_ascii_lowercase(),
+            host: bracket_host(host),
+            port: None,
+            prefix: Vec::new(),
+        }
+    }
+
+    /// Set the port, e.g. `8080`.
+    pub fn with_port(self, port: u16) -> Self {
+        UrlBase {
+            port: Some(port),
+            ..self
+        }
+    }
+
+    /// Set the path prefix, e.g. `/base`. Empty segments, e.g. from a trailing slash, are ignored.
+    pub fn with_prefix(self, prefix: &str) -> Self {
+        UrlBase {
+            prefix: raw::parse_path(prefix)
+                .filter(|seg| !seg.is_empty())
+                .map(|seg| seg.into_owned())
+                .collect(),
+            ..self
+        }
+    }
+
+    /// The scheme, e.g. `https`.
+    pub fn scheme(&self) -> &str {
+        &self.scheme
+    }
+
+    /// The host, with brackets around an IPv6 address.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// The port, if not the default for the scheme.
+    pub fn port(&self) -> Option<u16> {
+        self.port
+    }
+
+    /// Render an absolute URL for the given path and query string, with the path prefix prepended.
+    ///
+    /// `host` replaces the host of this base, keeping the scheme and port, e.g. for a route restricted to a host.
+    pub fn render_path_and_query(
+        &self,
+        host: Option<&str>,
+        path: &[PathSegment<'_>],
+        query: Option<&[QueryPair<'_>]>,
+    ) -> String {
+        let mut res = format!("{}://", self.scheme);
+        match host {
+            Some(host) => res.push_str(&bracket_host(host)),
+            None => res.push_str(&self.host),
+        }
+        if let Some(port) = self.port {
+            res.push_str(&format!(":{}", port));
+        }
+        res.push_str(&raw::render_path_and_query(
+            self.prefix
+                .iter()
+                .map(|seg| seg.as_str())
+                .chain(path.iter().map(|seg| seg.as_ref())),
+            query.map(|query| {
+                query
+                    .iter()
+                    .map(|(k, v)| (k.as_ref(), v.as_ref().map(|v| v.as_ref())))
+            }),
+        ));
+        res
+    }
+}
+
+/// Parses a URL such as `https://app.example.com:8443/base`, without a query string or fragment.
+impl FromStr for UrlBase {
+    type Err = InvalidUrlBase;
+
+    fn from_str(url: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = match url.find("://") {
+            Some(idx) => (&url[..idx], &url[idx + 3..]),
+            None => return Err(InvalidUrlBase("missing scheme")),
+        };
+        let valid_scheme = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+        if !valid_scheme {
+            return Err(InvalidUrlBase("invalid scheme"));
+        }
+        if rest.contains(['?', '#']) {
+            return Err(InvalidUrlBase("unexpected query string or fragment"));
+        }
+        let (authority, prefix) = match rest.find('/') {
+            Some(idx) => (&rest[..idx], &rest[idx..]),
+            None => (rest, ""),
+        };
+        if authority.contains('@') {
+            return Err(InvalidUrlBase("unexpected user information"));
+        }
+        // The port comes after the last colon, unless that's within an IPv6 address
+        let (host, port) = match authority.rfind(':') {
+            Some(idx) if !authority[idx..].contains(']') => {
+                let port = authority[idx + 1..]
+                    .parse()
+                    .map_err(|_| InvalidUrlBase("invalid port"))?;
+                (&authority[..idx], Some(port))
+            }
+            _ => (authority, None),
+        };
+        if host.is_empty() {
+            return Err(InvalidUrlBase("missing host"));
+        }
+        if host.starts_with('[') != host.ends_with(']') {
+            return Err(InvalidUrlBase("unbalanced brackets in host"));
+        }
+        Ok(UrlBase {
+            port,
+            ..UrlBase::new(scheme, host).with_prefix(prefix)
+        })
+    }
+}
+
+/// Wrap an IPv6 address in brackets, if it isn't already.
+fn bracket_host(host: &str) -> String {
+    if host.contains(':') && !host.starts_with('[') {
+        format!("[{}]", host)
+    } else {
+        host.to_owned()
+    }
+}
+
+/// Why parsing a [UrlBase] failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidUrlBase(&'static str);
+
+impl fmt::Display for InvalidUrlBase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid base URL: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidUrlBase {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(base: &UrlBase, path: &[&str]) -> String {
+        let path: Vec<PathSegment> = path.iter().map(|&seg| seg.into()).collect();
+        base.render_path_and_query(None, &path, None)
+    }
+
+    #[test]
+    fn parse_and_render() {
+        let base: UrlBase = "HTTPS://app.example.com:8443/a%20b/c/".parse().unwrap();
+        assert_eq!(base.scheme(), "https");
+        assert_eq!(base.port(), Some(8443));
+        assert_eq!(
+            render(&base, &["x", "y/z"]),
+            "https://app.example.com:8443/a%20b/c/x/y%2Fz"
+        );
+        assert_eq!(render(&base, &[]), "https://app.example.com:8443/a%20b/c");
+
+        let base: UrlBase = "http://example.com".parse().unwrap();
+        assert_eq!(render(&base, &[]), "http://example.com/");
+    }
+
+    #[test]
+    fn ipv6() {
+        let base: UrlBase = "http://[::1]:3000".parse().unwrap();
+        assert_eq!(base.host(), "[::1]");
+        assert_eq!(base.port(), Some(3000));
+        let base: UrlBase = "http://[::1]".parse().unwrap();
+        assert_eq!(base.port(), None);
+        assert_eq!(render(&base, &["x"]), "http://[::1]/x");
+        assert_eq!(
+            render(&UrlBase::new("http", "fe80::1"), &[]),
+            "http://[fe80::1]/"
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        for url in &[
+            "example.com",
+            "1http://example.com",
+            "http://",
+            "http://example.com:port",
+            "http://example.com/?x",
+            "http://user@example.com",
+            "http://[::1/",
+        ] {
+            assert!(url.parse::<UrlBase>().is_err(), "{}", url);
+        }
+    }
+}
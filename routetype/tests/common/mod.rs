@@ -0,0 +1,37 @@
+use routetype::{normalize::Normalization, raw, PathSegment, QueryMap, Route};
+use std::ops::Deref;
+
+/// Render a route through its [Route::path] and [Route::query] `Vec`s, bypassing [Route::render_to].
+pub fn render_via_vecs<R: Route>(route: &R) -> String {
+    routetype::raw::render_path_and_query(
+        route.path().iter().map(|seg| seg.as_ref()),
+        route
+            .query()
+            .as_ref()
+            .map(|query| query.iter().map(|(k, v)| (k.as_ref(), v.as_deref()))),
+    )
+}
+
+/// The work derived routes used to do before matching any variant: normalize the path into a `Vec` and always build the [QueryMap].
+pub fn parse_preamble_via_vecs(
+    path_and_query: &str,
+) -> Result<(Vec<PathSegment<'_>>, QueryMap<'_>), String> {
+    let (path, query) = raw::parse_path_and_query(path_and_query);
+    let (path, query) = Normalization::new().normalize_parse(path, query)?;
+    Ok((path, QueryMap::from_query_iter(query)))
+}
+
+/// Like [parse_preamble_via_vecs], but the way derived routes do it now: short paths are kept inline, and the [QueryMap] is only built if `uses_query`, i.e. a variant with query string fields matched the path.
+pub fn parse_preamble_inline(
+    path_and_query: &str,
+    uses_query: bool,
+) -> Result<(impl Deref<Target = [PathSegment<'_>]>, Option<QueryMap<'_>>), String> {
+    let (path, query) = raw::parse_path_and_query(path_and_query);
+    let (path, query) = Normalization::new().normalize_parse_inline(path, query)?;
+    let query = if uses_query {
+        Some(QueryMap::from_query_iter(query))
+    } else {
+        None
+    };
+    Ok((path, query))
+}
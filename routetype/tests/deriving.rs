@@ -1,3 +1,6 @@
+mod common;
+
+use common::{parse_preamble_inline, parse_preamble_via_vecs, render_via_vecs};
 use routetype::*;
 
 #[derive(Route, Clone, PartialEq, Debug)]
@@ -13,11 +16,17 @@ enum MyRoute {
     #[route("/goodbye/{}")]
     Goodbye(String),
     #[route("/?readiness")]
+    #[route(allow_overlap)]
     Readiness,
     #[route("/?poll={}")]
+    #[route(allow_overlap)]
     Poll(bool),
     #[route("/refresh?force=true")]
     Refresh,
+    #[route("/static/{path*}")]
+    Static { path: Vec<String> },
+    #[route("/numbers/{*}")]
+    Numbers(Vec<i32>),
 }
 
 #[test]
@@ -46,6 +55,15 @@ fn parse_style() {
     assert_eq!(MyRoute::parse_str("/style.css?foo"), Ok(MyRoute::Style));
 }
 
+#[test]
+fn allow_overlap_keeps_order() {
+    // Home ignores the query string, so it shadows Readiness and Poll, which are still rendered
+    assert_eq!(MyRoute::parse_str("/?readiness"), Ok(MyRoute::Home));
+    assert_eq!(MyRoute::parse_str("/?poll=true"), Ok(MyRoute::Home));
+    assert_eq!(MyRoute::Readiness.render(), "/?readiness");
+    assert_eq!(MyRoute::Poll(true).render(), "/?poll=true");
+}
+
 #[test]
 fn parse_hello() {
     assert_eq!(
@@ -102,3 +120,1316 @@ fn parse_normalize() {
         "/hello/-"
     );
 }
+
+#[derive(Route, Clone, PartialEq, Debug)]
+enum OrderedRoute {
+    #[route("/user/{}")]
+    UserId(i32),
+    #[route("/user/new")]
+    NewUser,
+    #[route("/user/{}")]
+    UserName(String),
+    #[route("/user/{}/posts")]
+    UserPosts(String),
+    #[route("/{}/posts")]
+    Posts(String),
+}
+
+#[test]
+fn first_match_wins() {
+    assert_eq!(
+        OrderedRoute::parse_str("/user/42"),
+        Ok(OrderedRoute::UserId(42))
+    );
+    assert_eq!(
+        OrderedRoute::parse_str("/user/new"),
+        Ok(OrderedRoute::NewUser)
+    );
+    assert_eq!(
+        OrderedRoute::parse_str("/user/alice"),
+        Ok(OrderedRoute::UserName("alice".to_owned()))
+    );
+    assert_eq!(
+        OrderedRoute::parse_str("/user/posts"),
+        Ok(OrderedRoute::UserName("posts".to_owned()))
+    );
+    assert_eq!(
+        OrderedRoute::parse_str("/user/alice/posts"),
+        Ok(OrderedRoute::UserPosts("alice".to_owned()))
+    );
+    assert_eq!(
+        OrderedRoute::parse_str("/blog/posts"),
+        Ok(OrderedRoute::Posts("blog".to_owned()))
+    );
+    assert_eq!(
+        OrderedRoute::parse_str("/user/alice/comments"),
+        Err(RouteError::NoMatch)
+    );
+}
+
+#[test]
+fn rest_capture() {
+    assert_eq!(
+        MyRoute::parse_str("/static/css/style.css"),
+        Ok(MyRoute::Static {
+            path: vec!["css".to_owned(), "style.css".to_owned()]
+        })
+    );
+    assert_eq!(
+        MyRoute::parse_str("/static"),
+        Ok(MyRoute::Static { path: vec![] })
+    );
+    assert_eq!(
+        MyRoute::Static {
+            path: vec!["a/b".to_owned(), "".to_owned(), "c d".to_owned()]
+        }
+        .render(),
+        "/static/a%2Fb/-/c%20d"
+    );
+    assert_eq!(
+        MyRoute::parse_str("/static/a%2Fb/-/c%20d"),
+        Ok(MyRoute::Static {
+            path: vec!["a/b".to_owned(), "".to_owned(), "c d".to_owned()]
+        })
+    );
+    assert_eq!(
+        MyRoute::parse_str("/numbers/1/2/3"),
+        Ok(MyRoute::Numbers(vec![1, 2, 3]))
+    );
+    assert_eq!(
+        MyRoute::parse_str("/numbers/1/two/3"),
+        Err(RouteError::NoMatch)
+    );
+}
+
+#[derive(Route, Clone, PartialEq, Debug)]
+enum AdminRoute {
+    #[route("/")]
+    Dashboard,
+    #[route("/users/{}")]
+    User(String),
+    #[route("/search?q={query}")]
+    Search { query: String },
+}
+
+#[derive(Route, Clone, PartialEq, Debug)]
+enum ParentRoute {
+    #[route("/")]
+    Home,
+    #[route("/admin/{sub*}")]
+    Admin {
+        #[route(nest)]
+        sub: AdminRoute,
+    },
+    #[route("/other/{}/{*}")]
+    Other(String, #[route(nest)] AdminRoute),
+    #[route("/admin/{*}")]
+    Fallback(Vec<String>),
+}
+
+#[test]
+fn nested_parse() {
+    assert_eq!(
+        ParentRoute::parse_str("/admin"),
+        Ok(ParentRoute::Admin {
+            sub: AdminRoute::Dashboard
+        })
+    );
+    assert_eq!(
+        ParentRoute::parse_str("/admin/users/-"),
+        Ok(ParentRoute::Admin {
+            sub: AdminRoute::User("".to_owned())
+        })
+    );
+    assert_eq!(
+        ParentRoute::parse_str("/admin/search?q=foo"),
+        Ok(ParentRoute::Admin {
+            sub: AdminRoute::Search {
+                query: "foo".to_owned()
+            }
+        })
+    );
+    assert_eq!(
+        ParentRoute::parse_str("/other/x/users/alice"),
+        Ok(ParentRoute::Other(
+            "x".to_owned(),
+            AdminRoute::User("alice".to_owned())
+        ))
+    );
+    assert_eq!(
+        ParentRoute::parse_str("/admin/unknown"),
+        Ok(ParentRoute::Fallback(vec!["unknown".to_owned()]))
+    );
+    assert_eq!(
+        ParentRoute::parse_str("/admin/users/alice/"),
+        Err(RouteError::NormalizationFailed(
+            "/admin/users/alice".to_owned()
+        ))
+    );
+}
+
+#[test]
+fn nested_render() {
+    let route = ParentRoute::Admin {
+        sub: AdminRoute::User("".to_owned()),
+    };
+    assert_eq!(route.render(), "/admin/users/-");
+    assert_eq!(ParentRoute::parse_str(&route.render()), Ok(route));
+
+    let route = ParentRoute::Other(
+        "-".to_owned(),
+        AdminRoute::Search {
+            query: "a&b".to_owned(),
+        },
+    );
+    assert_eq!(route.render(), "/other/--/search?q=a%26b");
+    assert_eq!(ParentRoute::parse_str(&route.render()), Ok(route));
+
+    assert_eq!(
+        ParentRoute::Admin {
+            sub: AdminRoute::Dashboard
+        }
+        .render(),
+        "/admin"
+    );
+}
+
+#[derive(Route, Clone, PartialEq, Debug)]
+#[route_config(normalize = "trailing_slash")]
+enum SlashAdminRoute {
+    #[route("/")]
+    Index,
+    #[route("/users/{}")]
+    User(u32),
+}
+
+#[derive(Route, Clone, PartialEq, Debug)]
+#[route_config(normalize = "trailing_slash")]
+enum SlashParentRoute {
+    #[route("/")]
+    Home,
+    #[route("/admin/{sub*}")]
+    Admin {
+        #[route(nest)]
+        sub: SlashAdminRoute,
+    },
+}
+
+#[test]
+fn nested_trailing_slash() {
+    let routes = [
+        SlashParentRoute::Home,
+        SlashParentRoute::Admin {
+            sub: SlashAdminRoute::Index,
+        },
+        SlashParentRoute::Admin {
+            sub: SlashAdminRoute::User(5),
+        },
+    ];
+    for route in &routes {
+        assert_eq!(
+            SlashParentRoute::parse_str(&route.render()).as_ref(),
+            Ok(route)
+        );
+    }
+    assert_eq!(routes[1].render(), "/admin/");
+    assert_eq!(routes[1].path(), vec!["admin", ""]);
+    assert_eq!(routes[2].render(), "/admin/users/5/");
+    assert_eq!(
+        SlashParentRoute::parse_str("/admin/users/5"),
+        Err(RouteError::NormalizationFailed(
+            "/admin/users/5/".to_owned()
+        ))
+    );
+    assert_eq!(
+        SlashParentRoute::parse_str("/admin"),
+        Err(RouteError::NormalizationFailed("/admin/".to_owned()))
+    );
+}
+
+#[derive(Route, Clone, PartialEq, Debug)]
+#[route_config(normalize = "trailing_slash, case_insensitive")]
+enum DirRoute {
+    #[route("/")]
+    Root,
+    #[route("/Docs/{}")]
+    Docs(String),
+    #[route("/docs")]
+    DocsIndex,
+}
+
+#[test]
+fn normalization_config() {
+    assert_eq!(DirRoute::parse_str("/"), Ok(DirRoute::Root));
+    assert_eq!(DirRoute::parse_str("/docs/"), Ok(DirRoute::DocsIndex));
+    assert_eq!(DirRoute::parse_str("/DOCS/"), Ok(DirRoute::DocsIndex));
+    assert_eq!(
+        DirRoute::parse_str("/docs/Intro/"),
+        Ok(DirRoute::Docs("Intro".to_owned()))
+    );
+    assert_eq!(
+        DirRoute::parse_str("/docs/intro"),
+        Err(RouteError::NormalizationFailed("/docs/intro/".to_owned()))
+    );
+    assert_eq!(DirRoute::Root.render(), "/");
+    assert_eq!(DirRoute::Docs("intro".to_owned()).render(), "/Docs/intro/");
+}
+
+#[test]
+fn diagnostics() {
+    use routetype::diagnostic::{Diagnostic, DiagnosticLocation};
+
+    let expected = Diagnostic {
+        variant: "Foo".to_owned(),
+        location: DiagnosticLocation::QueryKey("bar".to_owned()),
+        value: Some("fortytwo".to_owned()),
+        error: Some("invalid digit found in string".to_owned()),
+    };
+    assert_eq!(
+        MyRoute::parse_str_diagnostic("foo?bar=fortytwo"),
+        Err(RouteError::Invalid(Box::new(expected.clone())))
+    );
+    assert_eq!(
+        expected.to_string(),
+        "Foo: invalid value \"fortytwo\" for query string parameter \"bar\": invalid digit found in string"
+    );
+    assert_eq!(
+        MyRoute::parse_str_diagnostic("foo"),
+        Err(RouteError::Invalid(Box::new(Diagnostic::query_value(
+            "Foo", "bar", None
+        ))))
+    );
+    assert_eq!(
+        MyRoute::parse_str_diagnostic("/does/not/exist"),
+        Err(RouteError::NoMatch)
+    );
+    assert_eq!(
+        MyRoute::parse_str_diagnostic("/hello/alice"),
+        MyRoute::parse_str("/hello/alice")
+    );
+
+    // No variant has a path of this shape, so there is no closest match to describe
+    assert_eq!(
+        OrderedRoute::parse_str_diagnostic("/user/alice/comments"),
+        Err(RouteError::NoMatch)
+    );
+    // Failures within a nested route name both the parent and the child variant
+    assert_eq!(
+        ParentRoute::parse_str_diagnostic("/other/x/search"),
+        Err(RouteError::Invalid(Box::new(Diagnostic {
+            variant: "Other::Search".to_owned(),
+            location: DiagnosticLocation::QueryKey("q".to_owned()),
+            value: None,
+            error: Some("expected exactly one value".to_owned()),
+        })))
+    );
+}
+
+#[derive(Clone, PartialEq, Debug)]
+struct Page(u32);
+
+impl TryRoutePiece for Page {
+    type Error = &'static str;
+
+    fn try_parse_route_piece(s: &str) -> Result<Self, Self::Error> {
+        match s.parse() {
+            Ok(page) if page > 0 => Ok(Page(page)),
+            _ => Err("page must be a positive integer"),
+        }
+    }
+}
+
+impl std::fmt::Display for Page {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Only implements the `Option` based API, so diagnostics have no description.
+#[derive(Clone, PartialEq, Debug)]
+struct Even(u32);
+
+impl RoutePiece for Even {
+    fn parse_route_piece(s: &str) -> Option<Self> {
+        s.parse().ok().filter(|n| n % 2 == 0).map(Even)
+    }
+
+    fn render_route_piece(&self) -> std::borrow::Cow<'_, str> {
+        self.0.to_string().into()
+    }
+}
+
+#[derive(Route, Clone, PartialEq, Debug)]
+enum PagedRoute {
+    #[route("/list/{}")]
+    List(Page),
+    #[route("/even/{}")]
+    Even(Even),
+    #[route("/evens/{*}")]
+    Evens(Vec<Even>),
+}
+
+#[test]
+fn piece_errors() {
+    assert_eq!(
+        PagedRoute::parse_str("/list/3"),
+        Ok(PagedRoute::List(Page(3)))
+    );
+    assert_eq!(PagedRoute::parse_str("/list/0"), Err(RouteError::NoMatch));
+    match PagedRoute::parse_str_diagnostic("/list/0") {
+        Err(RouteError::Invalid(d)) => assert_eq!(
+            d.to_string(),
+            "List: invalid path segment 1 \"0\": page must be a positive integer"
+        ),
+        res => panic!("unexpected {:?}", res),
+    }
+    assert_eq!(Page::parse_route_piece("-1"), None);
+    assert_eq!(
+        Page::parse_route_piece_diagnostic("-1"),
+        Err(Some("page must be a positive integer".to_owned()))
+    );
+    assert_eq!(
+        bool::try_parse_route_piece("yes"),
+        Err(InvalidRoutePiece("true or false"))
+    );
+}
+
+#[test]
+fn option_only_piece() {
+    use routetype::diagnostic::Diagnostic;
+
+    assert_eq!(
+        PagedRoute::parse_str("/even/4"),
+        Ok(PagedRoute::Even(Even(4)))
+    );
+    assert_eq!(
+        PagedRoute::parse_str("/evens/2/6"),
+        Ok(PagedRoute::Evens(vec![Even(2), Even(6)]))
+    );
+    assert_eq!(PagedRoute::Even(Even(8)).render(), "/even/8");
+    assert_eq!(
+        PagedRoute::parse_str_diagnostic("/even/3"),
+        Err(RouteError::Invalid(Box::new(Diagnostic::path_segment(
+            "Even", 1, "3"
+        ))))
+    );
+    assert_eq!(
+        PagedRoute::parse_str_diagnostic("/evens/2/5"),
+        Err(RouteError::Invalid(Box::new(Diagnostic::path_segment(
+            "Evens", 1, "2/5"
+        ))))
+    );
+}
+
+#[derive(RoutePiece, Clone, Copy, PartialEq, Debug)]
+enum Lang {
+    En,
+    He,
+    #[piece("fr-ca")]
+    CanadianFrench,
+    SimplifiedChinese,
+}
+
+#[derive(RoutePiece, Clone, PartialEq, Debug)]
+struct UserId(u64);
+
+#[derive(Route, Clone, PartialEq, Debug)]
+enum LangRoute {
+    #[route("/{lang}/docs")]
+    Docs { lang: Lang },
+    #[route("/users/{}")]
+    User(UserId),
+}
+
+#[test]
+fn derive_piece() {
+    assert_eq!(
+        LangRoute::parse_str("/he/docs"),
+        Ok(LangRoute::Docs { lang: Lang::He })
+    );
+    assert_eq!(
+        LangRoute::Docs {
+            lang: Lang::CanadianFrench
+        }
+        .render(),
+        "/fr-ca/docs"
+    );
+    assert_eq!(
+        Lang::SimplifiedChinese.render_route_piece(),
+        "simplified-chinese"
+    );
+    assert_eq!(
+        Lang::parse_route_piece_diagnostic("de"),
+        Err(Some(
+            "expected one of \"en\", \"he\", \"fr-ca\", \"simplified-chinese\"".to_owned()
+        ))
+    );
+    assert_eq!(
+        LangRoute::parse_str("/users/42"),
+        Ok(LangRoute::User(UserId(42)))
+    );
+    assert_eq!(LangRoute::User(UserId(7)).render(), "/users/7");
+}
+
+#[derive(Route, Clone, PartialEq, Debug)]
+enum SearchRoute {
+    #[route("/search?q={q}&page={page}&lang={lang}")]
+    Search {
+        q: String,
+        #[route(default = 1)]
+        page: u32,
+        lang: Option<Lang>,
+    },
+    #[route("/tags?sort={}")]
+    Tags(#[route(default)] String),
+}
+
+#[test]
+fn optional_query() {
+    assert_eq!(
+        SearchRoute::parse_str("/search?q=rust"),
+        Ok(SearchRoute::Search {
+            q: "rust".to_owned(),
+            page: 1,
+            lang: None
+        })
+    );
+    assert_eq!(
+        SearchRoute::parse_str("/search?q=rust&page=3&lang=he"),
+        Ok(SearchRoute::Search {
+            q: "rust".to_owned(),
+            page: 3,
+            lang: Some(Lang::He)
+        })
+    );
+    assert_eq!(
+        SearchRoute::parse_str("/search?page=3"),
+        Err(RouteError::NoMatch)
+    );
+    assert_eq!(
+        SearchRoute::parse_str("/search?q=rust&page=3&page=4"),
+        Err(RouteError::NoMatch)
+    );
+    assert_eq!(
+        SearchRoute::Search {
+            q: "rust".to_owned(),
+            page: 1,
+            lang: None
+        }
+        .render(),
+        "/search?q=rust"
+    );
+    assert_eq!(
+        SearchRoute::Search {
+            q: "rust".to_owned(),
+            page: 2,
+            lang: Some(Lang::En)
+        }
+        .render(),
+        "/search?q=rust&page=2&lang=en"
+    );
+    assert_eq!(
+        SearchRoute::parse_str("/tags"),
+        Ok(SearchRoute::Tags(String::new()))
+    );
+    assert_eq!(SearchRoute::Tags(String::new()).render(), "/tags");
+    assert_eq!(
+        SearchRoute::Tags("name".to_owned()).render(),
+        "/tags?sort=name"
+    );
+}
+
+#[derive(Route, Clone, PartialEq, Debug)]
+enum FilterRoute {
+    #[route("/posts?tag={tags}")]
+    Posts { tags: Vec<String> },
+    #[route("/ids?ids={ids}")]
+    Ids {
+        #[route(separator = ",")]
+        ids: Vec<u32>,
+    },
+    #[route("/labels?labels={labels}")]
+    Labels {
+        #[route(separator = "|,")]
+        labels: Vec<String>,
+    },
+}
+
+#[test]
+fn repeated_query() {
+    let posts = FilterRoute::Posts {
+        tags: vec!["a".to_owned(), "b".to_owned()],
+    };
+    assert_eq!(
+        FilterRoute::parse_str("/posts?tag=a&tag=b"),
+        Ok(posts.clone())
+    );
+    assert_eq!(posts.render(), "/posts?tag=a&tag=b");
+    assert_eq!(
+        FilterRoute::parse_str("/posts"),
+        Ok(FilterRoute::Posts { tags: vec![] })
+    );
+    assert_eq!(FilterRoute::Posts { tags: vec![] }.render(), "/posts");
+    assert_eq!(
+        FilterRoute::parse_str("/posts?tag=a&tag"),
+        Err(RouteError::NoMatch)
+    );
+
+    let ids = FilterRoute::Ids { ids: vec![1, 2, 3] };
+    assert_eq!(FilterRoute::parse_str("/ids?ids=1,2,3"), Ok(ids.clone()));
+    assert_eq!(ids.render(), "/ids?ids=1,2,3");
+    assert_eq!(
+        FilterRoute::parse_str("/ids"),
+        Ok(FilterRoute::Ids { ids: vec![] })
+    );
+    match FilterRoute::parse_str_diagnostic("/ids?ids=1,x") {
+        Err(RouteError::Invalid(d)) => assert_eq!(
+            d.to_string(),
+            "Ids: invalid value \"1,x\" for query string parameter \"ids\": invalid digit found in string"
+        ),
+        res => panic!("unexpected {:?}", res),
+    }
+    assert!(matches!(
+        FilterRoute::parse_str_diagnostic("/ids?ids="),
+        Err(RouteError::Invalid(_))
+    ));
+
+    let labels = |labels: &[&str]| FilterRoute::Labels {
+        labels: labels.iter().map(|s| s.to_string()).collect(),
+    };
+    assert_eq!(
+        labels(&["a|,b", "c,d|e", "50%"]).render(),
+        "/labels?labels=a%257C%252Cb|,c%252Cd%257Ce|,50%2525"
+    );
+    assert_eq!(labels(&[""]).render(), "/labels?labels=");
+    assert_eq!(FilterRoute::parse_str("/labels?labels="), Ok(labels(&[""])));
+    assert_eq!(
+        FilterRoute::parse_str("/labels?labels=a|,|,b"),
+        Ok(labels(&["a", "", "b"]))
+    );
+}
+
+quickcheck::quickcheck! {
+    fn prop_round_trip_separated(labels: Vec<String>) -> bool {
+        let route = FilterRoute::Labels { labels };
+        FilterRoute::parse_str(&route.render()) == Ok(route)
+    }
+}
+
+#[derive(Route, Clone, PartialEq, Debug)]
+enum TrackedRoute {
+    #[route("/landing?campaign={campaign}")]
+    Landing {
+        campaign: String,
+        #[route(query_rest)]
+        extra: Vec<(String, Option<String>)>,
+    },
+    #[route("/")]
+    Home(#[route(query_rest)] Vec<(String, Option<String>)>),
+}
+
+#[derive(Route, Clone, PartialEq, Debug)]
+#[route_config(deny_unknown_query)]
+enum StrictRoute {
+    #[route("/style.css")]
+    Style,
+    #[route("/search?q={}")]
+    Search(String),
+}
+
+#[test]
+fn query_rest() {
+    let landing = TrackedRoute::Landing {
+        campaign: "spring".to_owned(),
+        extra: vec![
+            ("utm_source".to_owned(), Some("mail".to_owned())),
+            ("debug".to_owned(), None),
+        ],
+    };
+    assert_eq!(
+        TrackedRoute::parse_str("/landing?utm_source=mail&campaign=spring&debug"),
+        Ok(landing.clone())
+    );
+    assert_eq!(
+        landing.render(),
+        "/landing?campaign=spring&utm_source=mail&debug"
+    );
+    assert_eq!(TrackedRoute::parse_str("/"), Ok(TrackedRoute::Home(vec![])));
+    assert_eq!(TrackedRoute::Home(vec![]).render(), "/");
+
+    assert_eq!(MyRoute::parse_str("/style.css?foo"), Ok(MyRoute::Style));
+    assert_eq!(StrictRoute::parse_str("/style.css"), Ok(StrictRoute::Style));
+    assert_eq!(
+        StrictRoute::parse_str("/style.css?foo"),
+        Err(RouteError::NoMatch)
+    );
+    assert_eq!(
+        StrictRoute::parse_str("/search?q=x"),
+        Ok(StrictRoute::Search("x".to_owned()))
+    );
+    match StrictRoute::parse_str_diagnostic("/search?q=x&page=2") {
+        Err(RouteError::Invalid(d)) => assert_eq!(
+            d.to_string(),
+            "Search: invalid query string parameter \"page\": unknown parameter"
+        ),
+        res => panic!("unexpected {:?}", res),
+    }
+}
+
+#[derive(Route, Clone, PartialEq, Debug)]
+#[route("/users/{id}?tab={tab}")]
+struct UserRoute {
+    id: u64,
+    tab: Option<String>,
+}
+
+#[derive(Route, Clone, PartialEq, Debug)]
+#[route_config(normalize = "trailing_slash")]
+#[route("/health")]
+struct HealthRoute;
+
+#[derive(RouteQuery, Clone, PartialEq, Debug)]
+#[route("page={page}&per_page={per_page}")]
+struct Pagination {
+    #[route(default = 1)]
+    page: u32,
+    #[route(default = 20)]
+    per_page: u32,
+}
+
+#[derive(Route, Clone, PartialEq, Debug)]
+#[route_config(deny_unknown_query)]
+enum ListRoute {
+    #[route("/posts?author={author}")]
+    Posts {
+        author: Option<String>,
+        #[route(flatten)]
+        pagination: Pagination,
+    },
+    #[route("/tags")]
+    Tags(#[route(flatten)] Pagination),
+}
+
+#[test]
+fn struct_routes() {
+    let user = UserRoute {
+        id: 5,
+        tab: Some("posts".to_owned()),
+    };
+    assert_eq!(UserRoute::parse_str("/users/5?tab=posts"), Ok(user.clone()));
+    assert_eq!(user.render(), "/users/5?tab=posts");
+    assert_eq!(
+        UserRoute::parse_str("/users/five"),
+        Err(RouteError::NoMatch)
+    );
+    match UserRoute::parse_str_diagnostic("/users/five") {
+        Err(RouteError::Invalid(d)) => assert_eq!(d.variant, "UserRoute"),
+        res => panic!("unexpected {:?}", res),
+    }
+
+    assert_eq!(HealthRoute::parse_str("/health/"), Ok(HealthRoute));
+    assert_eq!(
+        HealthRoute::parse_str("/health"),
+        Err(RouteError::NormalizationFailed("/health/".to_owned()))
+    );
+    assert_eq!(HealthRoute.render(), "/health/");
+}
+
+#[test]
+fn route_query() {
+    let pagination = Pagination {
+        page: 2,
+        per_page: 20,
+    };
+    assert_eq!(
+        Pagination::parse_query_str("page=2"),
+        Ok(pagination.clone())
+    );
+    assert_eq!(pagination.render_query_str(), "page=2");
+
+    assert_eq!(
+        ListRoute::parse_str("/posts?page=3&author=alice"),
+        Ok(ListRoute::Posts {
+            author: Some("alice".to_owned()),
+            pagination: Pagination {
+                page: 3,
+                per_page: 20
+            },
+        })
+    );
+    assert_eq!(
+        ListRoute::Tags(Pagination {
+            page: 1,
+            per_page: 50
+        })
+        .render(),
+        "/tags?per_page=50"
+    );
+    assert_eq!(
+        ListRoute::parse_str("/tags?per_page=50"),
+        Ok(ListRoute::Tags(Pagination {
+            page: 1,
+            per_page: 50
+        }))
+    );
+    assert_eq!(
+        ListRoute::parse_str("/tags?sort=name"),
+        Err(RouteError::NoMatch)
+    );
+    match ListRoute::parse_str_diagnostic("/tags?page=x") {
+        Err(RouteError::Invalid(d)) => assert_eq!(
+            d.to_string(),
+            "Tags: invalid value \"x\" for query string parameter \"page\": invalid digit found in string"
+        ),
+        res => panic!("unexpected {:?}", res),
+    }
+}
+
+#[derive(Route, Clone, PartialEq, Debug)]
+enum ReorderedRoute {
+    #[route("/repos/{owner}/{repo}?ref={git_ref}")]
+    Repo {
+        git_ref: String,
+        repo: String,
+        owner: String,
+    },
+}
+
+#[test]
+fn named_field_order() {
+    let route = ReorderedRoute::Repo {
+        git_ref: "main".to_owned(),
+        repo: "routetype".to_owned(),
+        owner: "snoyberg".to_owned(),
+    };
+    assert_eq!(route.render(), "/repos/snoyberg/routetype?ref=main");
+    assert_eq!(
+        ReorderedRoute::parse_str("/repos/snoyberg/routetype?ref=main"),
+        Ok(route)
+    );
+}
+
+#[derive(Route, Clone, PartialEq, Debug)]
+enum AffixRoute {
+    #[route("/files/{name}.json")]
+    Json { name: String },
+    #[route("/files/{name}")]
+    File { name: String },
+    #[route("/v{}/status")]
+    Status(u32),
+    #[route("/@{user}")]
+    Profile { user: String },
+}
+
+#[test]
+fn segment_affixes() {
+    let json = AffixRoute::Json {
+        name: "data".to_owned(),
+    };
+    assert_eq!(json.render(), "/files/data.json");
+    assert_eq!(AffixRoute::parse_str("/files/data.json"), Ok(json));
+    assert_eq!(
+        AffixRoute::parse_str("/files/data.txt"),
+        Ok(AffixRoute::File {
+            name: "data.txt".to_owned()
+        })
+    );
+    assert_eq!(AffixRoute::Status(2).render(), "/v2/status");
+    assert_eq!(
+        AffixRoute::parse_str("/v2/status"),
+        Ok(AffixRoute::Status(2))
+    );
+    assert_eq!(AffixRoute::parse_str("/2/status"), Err(RouteError::NoMatch));
+    match AffixRoute::parse_str_diagnostic("/vtwo/status") {
+        Err(RouteError::Invalid(d)) => {
+            assert_eq!(d.variant, "Status");
+            assert_eq!(d.value.as_deref(), Some("vtwo"));
+        }
+        res => panic!("unexpected {:?}", res),
+    }
+    let profile = AffixRoute::Profile {
+        user: "alice".to_owned(),
+    };
+    assert_eq!(profile.render(), "/@alice");
+    assert_eq!(AffixRoute::parse_str("/@alice"), Ok(profile));
+}
+
+fn is_numeric(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn is_short(tag: &Option<String>) -> bool {
+    match tag {
+        Some(tag) => tag.len() <= 8,
+        None => true,
+    }
+}
+
+#[derive(Route, Clone, PartialEq, Debug)]
+enum ValidatedRoute {
+    #[route("/user/{id}")]
+    UserId {
+        #[route(validate = "is_numeric")]
+        id: String,
+    },
+    #[route("/user/{name}")]
+    UserName { name: String },
+    #[route("/posts?tag={tag}")]
+    Posts {
+        #[route(validate = "is_short")]
+        tag: Option<String>,
+    },
+}
+
+#[test]
+fn validate_fields() {
+    assert_eq!(
+        ValidatedRoute::parse_str("/user/0042"),
+        Ok(ValidatedRoute::UserId {
+            id: "0042".to_owned()
+        })
+    );
+    assert_eq!(
+        ValidatedRoute::parse_str("/user/alice"),
+        Ok(ValidatedRoute::UserName {
+            name: "alice".to_owned()
+        })
+    );
+    assert_eq!(
+        ValidatedRoute::parse_str("/posts"),
+        Ok(ValidatedRoute::Posts { tag: None })
+    );
+    assert_eq!(
+        ValidatedRoute::parse_str("/posts?tag=rust"),
+        Ok(ValidatedRoute::Posts {
+            tag: Some("rust".to_owned())
+        })
+    );
+    assert_eq!(
+        ValidatedRoute::parse_str("/posts?tag=much-too-long"),
+        Err(RouteError::NoMatch)
+    );
+}
+
+#[derive(Route, Clone, PartialEq, Debug)]
+#[route_prefix("/api/v2")]
+enum ApiRoute {
+    #[route("/?version")]
+    Version,
+    #[route("/")]
+    Root,
+    #[route("/users/{}")]
+    User(u32),
+}
+
+#[test]
+fn route_prefix() {
+    assert_eq!(ApiRoute::Root.render(), "/api/v2");
+    assert_eq!(ApiRoute::parse_str("/api/v2"), Ok(ApiRoute::Root));
+    assert_eq!(ApiRoute::User(5).render(), "/api/v2/users/5");
+    assert_eq!(
+        ApiRoute::parse_str("/api/v2/users/5"),
+        Ok(ApiRoute::User(5))
+    );
+    assert_eq!(ApiRoute::parse_str("/users/5"), Err(RouteError::NoMatch));
+    assert_eq!(ApiRoute::Version.render(), "/api/v2?version");
+}
+
+#[test]
+fn mounted() {
+    let mount = Mount::new("/proxy/app/");
+    let route = mount
+        .parse_str::<ApiRoute>("/proxy/app/api/v2/users/5")
+        .unwrap();
+    assert_eq!(*route.route(), ApiRoute::User(5));
+    assert_eq!(route.render(), "/proxy/app/api/v2/users/5");
+    assert_eq!(route.to_string(), route.render());
+    assert_eq!(route.render_url(), route.render());
+    let home = mount.mount(MyRoute::Home);
+    assert_eq!(home.to_string(), "/proxy/app");
+    assert_eq!(home.to_string(), home.render());
+    assert_eq!(home.render_from_host(None), "/proxy/app");
+    let dashboard = mount.mount(HostRoute::Dashboard("acme".to_owned(), 5));
+    assert_eq!(dashboard.to_string(), dashboard.render());
+    assert_eq!(
+        dashboard.render_from_host(Some("www.example.com:8080")),
+        "//acme.example.com:8080/proxy/app/dashboard/5"
+    );
+    assert_eq!(
+        dashboard.render_from_host(Some("acme.example.com")),
+        "/proxy/app/dashboard/5"
+    );
+    assert_eq!(
+        mount.parse_str::<ApiRoute>("/api/v2/users/5"),
+        Err(RouteError::NoMatch)
+    );
+    assert_eq!(
+        mount.parse_str::<MyRoute>("/proxy/app/hello/alice/"),
+        Err(RouteError::NormalizationFailed(
+            "/proxy/app/hello/alice".to_owned()
+        ))
+    );
+    assert_eq!(
+        mount.parse_str::<MyRoute>("/proxy/app/"),
+        Err(RouteError::NormalizationFailed("/proxy/app".to_owned()))
+    );
+    assert_eq!(
+        mount
+            .parse_str::<MyRoute>("/proxy/app")
+            .map(Mounted::into_route),
+        Ok(MyRoute::Home)
+    );
+    // The prefix is normalized too
+    assert_eq!(
+        mount.parse_str::<MyRoute>("/proxy//app/hello/alice?x=1"),
+        Err(RouteError::NormalizationFailed(
+            "/proxy/app/hello/alice?x=1".to_owned()
+        ))
+    );
+    assert_eq!(
+        mount.parse_str::<MyRoute>("//proxy/app//hello/alice/"),
+        Err(RouteError::NormalizationFailed(
+            "/proxy/app/hello/alice".to_owned()
+        ))
+    );
+    assert_eq!(
+        mount.parse_str::<MyRoute>("/proxy//app/nothing"),
+        Err(RouteError::NoMatch)
+    );
+
+    let get = RequestInfo::method("GET");
+    assert_eq!(
+        mount
+            .parse_str_request::<MethodRoute>(get, "/proxy/app/items")
+            .map(Mounted::into_route),
+        Ok(MethodRoute::ListItems)
+    );
+    assert_eq!(
+        mount.parse_str_request::<MethodRoute>(get, "/proxy/app/items/5"),
+        Err(RouteError::MethodNotAllowed {
+            allowed: vec!["PUT", "DELETE"]
+        })
+    );
+    assert_eq!(
+        mount
+            .parse_str_request::<HostRoute>(
+                get.with_host("acme.example.com"),
+                "/proxy/app/dashboard/5"
+            )
+            .map(Mounted::into_route),
+        Ok(HostRoute::Dashboard("acme".to_owned(), 5))
+    );
+    assert!(matches!(
+        mount.parse_str_request_diagnostic::<HostRoute>(
+            get.with_host("acme.example.com"),
+            "/proxy/app/dashboard/five"
+        ),
+        Err(RouteError::Invalid(_))
+    ));
+}
+
+#[derive(Route, Clone, PartialEq, Debug)]
+enum MethodRoute {
+    #[route(GET, "/items")]
+    ListItems,
+    #[route(POST, "/items")]
+    CreateItem,
+    #[route(PUT, DELETE, "/items/{}")]
+    ModifyItem(u32),
+    #[route("/any")]
+    Any,
+    #[route("/nested/{sub*}")]
+    Nested {
+        #[route(nest)]
+        sub: NestedMethodRoute,
+    },
+}
+
+#[derive(Route, Clone, PartialEq, Debug)]
+enum NestedMethodRoute {
+    #[route(PATCH, "/")]
+    Root,
+}
+
+#[test]
+fn http_methods() {
+    assert_eq!(
+        MethodRoute::parse_str_method("GET", "/items"),
+        Ok(MethodRoute::ListItems)
+    );
+    assert_eq!(
+        MethodRoute::parse_str_method("HEAD", "/items"),
+        Ok(MethodRoute::ListItems)
+    );
+    assert_eq!(
+        MethodRoute::parse_str_method("POST", "/items"),
+        Ok(MethodRoute::CreateItem)
+    );
+    assert_eq!(
+        MethodRoute::parse_str_method("DELETE", "/items"),
+        Err(RouteError::MethodNotAllowed {
+            allowed: vec!["GET", "HEAD", "POST"]
+        })
+    );
+    assert_eq!(
+        MethodRoute::parse_str_method("DELETE", "/items/5"),
+        Ok(MethodRoute::ModifyItem(5))
+    );
+    assert_eq!(
+        MethodRoute::parse_str_method("GET", "/items/five"),
+        Err(RouteError::NoMatch)
+    );
+    assert_eq!(
+        MethodRoute::parse_str_method("OPTIONS", "/any"),
+        Ok(MethodRoute::Any)
+    );
+    assert_eq!(
+        MethodRoute::parse_str_method("GET", "/nested"),
+        Err(RouteError::MethodNotAllowed {
+            allowed: vec!["PATCH"]
+        })
+    );
+    assert_eq!(
+        MethodRoute::parse_str_method("PATCH", "/nested"),
+        Ok(MethodRoute::Nested {
+            sub: NestedMethodRoute::Root
+        })
+    );
+    // Method unaware parsing ignores the methods
+    assert_eq!(MethodRoute::parse_str("/items"), Ok(MethodRoute::ListItems));
+}
+
+#[derive(Route, Clone, PartialEq, Debug)]
+enum HostRoute {
+    #[route(host = "{}.example.com")]
+    #[route("/dashboard/{}")]
+    Dashboard(String, u32),
+    #[route(host = "www.example.com")]
+    #[route("/")]
+    Home,
+    #[route(host = "api-{version}.example.com")]
+    #[route("/")]
+    Api { version: u8 },
+    #[route("/")]
+    AnyHost,
+}
+
+#[test]
+fn host_routes() {
+    let host = |host| RequestInfo::method("GET").with_host(host);
+    assert_eq!(
+        HostRoute::parse_str_request(host("Acme.Example.com:8080"), "/dashboard/5"),
+        Ok(HostRoute::Dashboard("acme".to_owned(), 5))
+    );
+    assert_eq!(
+        HostRoute::parse_str_request(host("www.example.com"), "/"),
+        Ok(HostRoute::Home)
+    );
+    assert_eq!(
+        HostRoute::parse_str_request(host("api-2.example.com"), "/"),
+        Ok(HostRoute::Api { version: 2 })
+    );
+    assert_eq!(
+        HostRoute::parse_str_request(host("api-two.example.com"), "/"),
+        Ok(HostRoute::AnyHost)
+    );
+    assert_eq!(
+        HostRoute::parse_str_request(host("example.com"), "/dashboard/5"),
+        Err(RouteError::NoMatch)
+    );
+    // Like methods, hosts without fields aren't checked without a host, but fields can't be parsed
+    assert_eq!(HostRoute::parse_str("/"), Ok(HostRoute::Home));
+    assert_eq!(
+        HostRoute::parse_str(&HostRoute::Home.render()),
+        Ok(HostRoute::Home)
+    );
+    assert_eq!(
+        HostRoute::parse_str("/dashboard/5"),
+        Err(RouteError::NoMatch)
+    );
+
+    let dashboard = HostRoute::Dashboard("acme".to_owned(), 5);
+    assert_eq!(dashboard.host(), Some("acme.example.com".to_owned()));
+    assert_eq!(dashboard.render(), "/dashboard/5");
+    assert_eq!(
+        dashboard.render_from_host(Some("acme.example.com:8080")),
+        "/dashboard/5"
+    );
+    assert_eq!(
+        dashboard.render_from_host(Some("www.example.com")),
+        "//acme.example.com/dashboard/5"
+    );
+    assert_eq!(
+        dashboard.render_from_host(Some("www.example.com:8080")),
+        "//acme.example.com:8080/dashboard/5"
+    );
+    assert_eq!(
+        dashboard.render_url_from_host(Some("www.example.com")),
+        dashboard.render_from_host(Some("www.example.com"))
+    );
+    let mut rendered = String::new();
+    dashboard.render_url_to(&mut rendered).unwrap();
+    assert_eq!(rendered, dashboard.render());
+    // Hosts are compared in lowercase, so rendering agrees with parsing
+    let upper = HostRoute::Dashboard("Acme".to_owned(), 5);
+    assert_eq!(upper.host(), Some("acme.example.com".to_owned()));
+    assert_eq!(
+        upper.render_from_host(Some("ACME.example.com")),
+        "/dashboard/5"
+    );
+    assert_eq!(
+        HostRoute::Api { version: 2 }.render_from_host(None),
+        "//api-2.example.com/"
+    );
+    assert_eq!(HostRoute::AnyHost.host(), None);
+}
+
+#[test]
+fn render_absolute() {
+    let base = UrlBase::new("https", "example.com").with_prefix("/app/");
+    assert_eq!(
+        ApiRoute::User(5).render_absolute(&base),
+        "https://example.com/app/api/v2/users/5"
+    );
+    assert_eq!(
+        ApiRoute::Version.render_absolute(&base),
+        "https://example.com/app/api/v2?version"
+    );
+    assert_eq!(
+        HostRoute::Dashboard("acme".to_owned(), 5).render_absolute(&base.clone().with_port(8443)),
+        "https://acme.example.com:8443/app/dashboard/5"
+    );
+    assert_eq!(
+        Mount::new("/proxy")
+            .mount(ApiRoute::Root)
+            .render_absolute(&"http://[::1]:3000".parse().unwrap()),
+        "http://[::1]:3000/proxy/api/v2"
+    );
+}
+
+#[test]
+fn fragments() {
+    let route = ApiRoute::User(5).with_fragment("posts <2>");
+    assert_eq!(route.fragment(), "posts <2>");
+    assert_eq!(route.render(), "/api/v2/users/5#posts%20%3C2%3E");
+    assert_eq!(route.render_url(), route.render());
+    assert_eq!(
+        route.render_absolute(&UrlBase::new("https", "example.com")),
+        "https://example.com/api/v2/users/5#posts%20%3C2%3E"
+    );
+    assert_eq!(*route.route(), ApiRoute::User(5));
+    assert_eq!(route.to_string(), route.render());
+    assert_eq!(route.render_from_host(None), route.render());
+    let home = MyRoute::Home.with_fragment("x");
+    assert_eq!(home.to_string(), "/#x");
+    assert_eq!(home.to_string(), home.render());
+    let mounted = RouteWithFragment::new(Mount::new("/app").mount(ApiRoute::Version), "");
+    assert_eq!(mounted.render(), "/app/api/v2?version#");
+    assert_eq!(mounted.to_string(), mounted.render());
+    let dashboard = HostRoute::Dashboard("acme".to_owned(), 5).with_fragment("top");
+    assert_eq!(dashboard.to_string(), dashboard.render());
+    assert_eq!(
+        dashboard.render_from_host(Some("www.example.com")),
+        "//acme.example.com/dashboard/5#top"
+    );
+    let mounted = RouteWithFragment::new(
+        Mount::new("/app").mount(HostRoute::Dashboard("acme".to_owned(), 5)),
+        "top",
+    );
+    assert_eq!(
+        mounted.render_from_host(Some("www.example.com")),
+        "//acme.example.com/app/dashboard/5#top"
+    );
+}
+
+#[derive(Route, Clone, PartialEq, Debug)]
+#[route_config(no_display)]
+enum OwnDisplayRoute {
+    #[route("/")]
+    Home,
+}
+
+impl std::fmt::Display for OwnDisplayRoute {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("home")
+    }
+}
+
+#[test]
+fn display() {
+    let admin = |sub| ParentRoute::Admin { sub };
+    let parents = vec![
+        ParentRoute::Home,
+        admin(AdminRoute::Dashboard),
+        admin(AdminRoute::User("-".to_owned())),
+        admin(AdminRoute::Search {
+            query: "a&b".to_owned(),
+        }),
+        ParentRoute::Other("x y".to_owned(), AdminRoute::Dashboard),
+        ParentRoute::Fallback(vec!["".to_owned(), "--".to_owned()]),
+    ];
+    for route in &parents {
+        assert_eq!(route.to_string(), render_via_vecs(route));
+    }
+    for route in &[
+        DirRoute::Root,
+        DirRoute::Docs("a/b".to_owned()),
+        DirRoute::DocsIndex,
+    ] {
+        assert_eq!(route.to_string(), render_via_vecs(route));
+    }
+    assert_eq!(HealthRoute.to_string(), "/health/");
+    let json = AffixRoute::Json {
+        name: "data".to_owned(),
+    };
+    assert_eq!(json.to_string(), render_via_vecs(&json));
+    for route in &[
+        SlashParentRoute::Home,
+        SlashParentRoute::Admin {
+            sub: SlashAdminRoute::Index,
+        },
+        SlashParentRoute::Admin {
+            sub: SlashAdminRoute::User(3),
+        },
+    ] {
+        assert_eq!(route.to_string(), render_via_vecs(route));
+    }
+    let posts = ListRoute::Posts {
+        author: Some("me".to_owned()),
+        pagination: Pagination {
+            page: 2,
+            per_page: 20,
+        },
+    };
+    assert_eq!(posts.to_string(), render_via_vecs(&posts));
+    let landing = TrackedRoute::Landing {
+        campaign: "spring".to_owned(),
+        extra: vec![("debug".to_owned(), None)],
+    };
+    assert_eq!(landing.to_string(), render_via_vecs(&landing));
+    assert_eq!(
+        format!("<a href='{}'>", ApiRoute::Version),
+        "<a href='/api/v2?version'>"
+    );
+    assert_eq!(OwnDisplayRoute::Home.to_string(), "home");
+    assert_eq!(OwnDisplayRoute::Home.render(), "/");
+}
+
+#[test]
+fn parse_preambles_agree() {
+    for input in &["/", "/users/42/posts/a%20b", "/search?q=a&q=b&page=2&x"] {
+        let (vec_path, vec_query) = parse_preamble_via_vecs(input).unwrap();
+        let (inline_path, inline_query) = parse_preamble_inline(input, true).unwrap();
+        assert_eq!(vec_path, &*inline_path);
+        let inline_query = inline_query.unwrap();
+        for key in &["q", "page", "x", "missing"] {
+            assert_eq!(vec_query.get_all(key), inline_query.get_all(key));
+            assert_eq!(vec_query.contains(key), inline_query.contains(key));
+        }
+        assert!(parse_preamble_inline(input, false).unwrap().1.is_none());
+    }
+    assert_eq!(
+        parse_preamble_via_vecs("/users//42/").unwrap_err(),
+        parse_preamble_inline("/users//42/", false).err().unwrap()
+    );
+}
@@ -15,11 +15,11 @@ impl Route for BoringRoute {
         }
     }
 
-    fn path(&self) -> Vec<PathSegment> {
+    fn path(&self) -> Vec<PathSegment<'_>> {
         vec![]
     }
 
-    fn query(&self) -> Option<Vec<QueryPair>> {
+    fn query(&self) -> Option<Vec<QueryPair<'_>>> {
         None
     }
 }
@@ -36,3 +36,24 @@ fn boring_parse_render() {
 fn boring_parse_failure() {
     assert_eq!(BoringRoute::parse_str("hello"), Err(RouteError::NoMatch));
 }
+
+#[test]
+fn error_with_prefix() {
+    let prefix = ["admin", "users"];
+    assert_eq!(
+        RouteError::NormalizationFailed("/alice".to_owned()).with_prefix(prefix.iter().copied()),
+        RouteError::NormalizationFailed("/admin/users/alice".to_owned())
+    );
+    assert_eq!(
+        RouteError::NormalizationFailed("/".to_owned()).with_prefix(prefix.iter().copied()),
+        RouteError::NormalizationFailed("/admin/users".to_owned())
+    );
+    assert_eq!(
+        RouteError::NormalizationFailed("/?foo".to_owned()).with_prefix(prefix.iter().copied()),
+        RouteError::NormalizationFailed("/admin/users?foo".to_owned())
+    );
+    assert_eq!(
+        RouteError::NoMatch.with_prefix(prefix.iter().copied()),
+        RouteError::NoMatch
+    );
+}
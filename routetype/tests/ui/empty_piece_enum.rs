@@ -0,0 +1,6 @@
+use routetype::RoutePiece;
+
+#[derive(RoutePiece, Clone, PartialEq, Debug)]
+enum Never {}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use routetype::Route;
+
+#[derive(Route, Clone, PartialEq, Debug)]
+enum MyRoute {
+    #[route("/")]
+    Home,
+    Missing,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use routetype::Route;
+
+#[derive(Route, Clone, PartialEq, Debug)]
+enum MyRoute {
+    #[route("/hello/{nmae}")]
+    Hello { name: String },
+}
+
+fn main() {}
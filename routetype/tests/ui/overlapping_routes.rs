@@ -0,0 +1,15 @@
+use routetype::Route;
+
+#[derive(Route, Clone, PartialEq, Debug)]
+enum MyRoute {
+    #[route("/hello/{name}")]
+    Hello { name: String },
+    #[route("/hello/admin")]
+    Admin,
+    #[route("/files/{*}")]
+    Files(Vec<String>),
+    #[route("/files/readme?lang={lang}")]
+    Readme { lang: String },
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use routetype::Route;
+
+#[derive(Route, Clone, PartialEq, Debug)]
+enum MyRoute {
+    #[route("/users/{id}")]
+    User(u32),
+    #[route("/posts/{}")]
+    Post { id: u32 },
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use routetype::Route;
+
+#[derive(Route, Clone, PartialEq, Debug)]
+enum MyRoute {
+    #[route("/range/{start}-{end}")]
+    Range { start: u32, end: u32 },
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use routetype::Route;
+
+#[derive(Route, Clone, PartialEq, Debug)]
+enum MyRoute {
+    #[route("/users/{user_id}")]
+    User { id: u32 },
+}
+
+fn main() {}